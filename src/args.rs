@@ -0,0 +1,401 @@
+use rust_8::memory::Layout;
+use rust_8::quirks::{Profile, Quirks};
+use rust_8::rng::RngMode;
+use rust_8::scheduler::Speed;
+use rust_8::sound::{Beeper, Waveform};
+
+use crate::sdl::palette::Palette;
+use crate::sdl::upscale::{Overlay, Smoothing};
+use crate::sdl::video::Scaling;
+
+//------------------------------------------Command line-------------------------------------------
+//what to do when the program runs an opcode nothing defines
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BadOpPolicy {
+    //report it and quit
+    Halt,
+    //report it and carry on with the next instruction
+    Skip,
+    //stop in the debugger with pc still on the bad opcode
+    Debug,
+}
+
+impl BadOpPolicy {
+    pub fn from_name(name: &str) -> Option<BadOpPolicy> {
+        match name.to_lowercase().as_str() {
+            "halt" => Some(BadOpPolicy::Halt),
+            "skip" => Some(BadOpPolicy::Skip),
+            "debug" | "debugger" => Some(BadOpPolicy::Debug),
+            _ => None,
+        }
+    }
+}
+
+pub struct Args {
+    pub rom: String,
+    pub debug: bool,
+    //print every instruction as it runs
+    pub trace: bool,
+    pub quirks: Quirks,
+    //deepest CALL nesting allowed, None is unlimited
+    pub stack_depth: Option<usize>,
+    //where the stack and registers live
+    pub layout: Layout,
+    pub bad_op: BadOpPolicy,
+    pub speed: Speed,
+    //let the display refresh pace presenting instead of sleeping
+    pub vsync: bool,
+    //starting window size in window pixels per lores pixel
+    pub scale: u32,
+    pub scaling: Scaling,
+    pub fullscreen: bool,
+    pub palette: Palette,
+    //afterglow strengths, 0 to 1
+    pub phosphor: f32,
+    pub blend: f32,
+    //cpu side upscaling before the picture is shown
+    pub smoothing: Smoothing,
+    pub overlay: Overlay,
+    //run known VIP machine code routines natively when 0NNN calls them
+    pub sys: bool,
+    pub beeper: Beeper,
+    pub rewind_seconds: usize,
+    //None picks a fresh seed
+    pub seed: Option<u64>,
+    pub rng_mode: RngMode,
+    //movie file to write on exit / to play back from power on
+    pub record: Option<String>,
+    pub play: Option<String>,
+    //config file to read instead of rust-8.cfg
+    pub config: Option<String>,
+    //keypad preset, then single keys bound on top of it as (chip8 key, binding)
+    pub keymap: Option<String>,
+    pub keys: Vec<(u8, String)>,
+    //controller button layout, then single buttons bound on top as (button name, chip8 key)
+    pub pad: Option<String>,
+    pub buttons: Vec<(String, u8)>,
+    //shake controllers while the sound timer runs
+    pub rumble: bool,
+}
+
+//options that take no value, config files turn them on with name = on
+pub const FLAGS: &[&str] = &[
+    "debug",
+    "trace",
+    "vsync",
+    "fullscreen",
+    "sys",
+    "mute",
+    "rumble",
+];
+
+//options that take a value, anything else in a config file is refused instead of passed on
+pub const OPTIONS: &[&str] = &[
+    "profile", "quirk", "stack", "layout", "ipf", "hz", "scale", "scaling", "palette", "phosphor",
+    "blend", "smooth", "overlay", "bad-op", "wave", "tone", "volume", "rewind", "seed", "rng",
+    "record", "play", "config", "keymap", "key", "pad", "button",
+];
+
+pub const USAGE: &str = "usage: rust-8 <rom.ch8> [debug] [options]
+  --debug                  start in the debugger
+  --trace                  print every instruction as it runs
+  --profile <name>         quirks profile: vip, chip48, schip, xochip
+  --quirk <name>=<on|off>  override one quirk: shift, memory, jump, vfreset, clip, vblank,
+                           keypress
+  --stack <n|unlimited>    call stack depth, defaults to 12 for vip and 16 otherwise
+  --layout <separate|vip>  vip keeps the stack at 0xEA0 and V0-VF at 0xEF0 in emulated memory
+  --ipf <n>                run n instructions every 60Hz frame
+  --hz <n>                 run n instructions per second instead, 500 if neither is given
+  --vsync                  wait for the display refresh instead of sleeping between frames
+  --scale <n>              start with a window n times the size of the lores screen, 10 if not
+                           given. the window can be resized
+  --scaling <fit|integer>  fit the picture to the window, or only scale it by whole numbers
+  --fullscreen             start fullscreen (F11 toggles)
+  --palette <name|colours> default, green, amber, lcd, contrast, colourblind, cga, or 2-16
+                           comma separated hex colours: background, plane 1, plane 2, both...
+                           (P cycles palettes)
+  --phosphor <0-99>        percent of a dark pixel's colour kept each frame, fading it out
+                           like a crt instead of flickering. 0 (off) if not given
+  --blend <0-100>          how strongly pixels lit last frame still show, 100 ORs the last
+                           two frames. 0 (off) if not given
+  --smooth <name>          round off pixel edges: none, scale2x (epx), scale3x
+  --overlay <name>         draw lines over the pixels: none, scanlines, grid
+  --bad-op <policy>        on an unknown opcode: halt, skip, or debug to open the debugger
+  --sys                    emulate known VIP machine code routines called with 0NNN
+  --wave <name>            beeper waveform: square, sine, triangle
+  --tone <hz>              beeper frequency
+  --volume <0-100>         output volume
+  --mute                   start muted (M toggles)
+  --rewind <seconds>       history kept for hold-to-rewind (backspace), 0 turns it off
+  --seed <n>               seed for CXNN random numbers, the seed used is printed at startup
+  --rng <splitmix|counter> random number generator, counter is a cheap 8-bit style one
+  --record <file>          record keypad input per frame into a movie, written on exit
+  --play <file>            play a recorded movie back
+  --config <file>          read options from this file instead of rust-8.cfg
+  --keymap <name>          keypad layout: qwerty (default), numpad, vip
+  --key <hex>=<keys>       bind a chip8 key to comma separated physical keys (sdl scancode
+                           names like W or Keypad 7), or key:<name> for the key labelled so
+  --pad <name>             controller layout: default, pong, tetris, invaders, brix, breakout.
+                           picked from the rom's file name when not given
+  --button <name>=<hex>    bind a controller button (a, b, x, y, dpup, dpleft, start...)
+  --rumble                 rumble controllers while the sound timer runs";
+
+impl Args {
+    //first bare argument is the rom, any second bare argument starts the debugger like it always has
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let mut rom: Option<String> = None;
+        let mut debug = false;
+        let mut trace = false;
+        let mut quirks = Quirks::default();
+        let mut profile_depth = Profile::XoChip.stack_depth();
+        let mut stack_depth = None;
+        let mut layout = Layout::Separate;
+        let mut bad_op = BadOpPolicy::Halt;
+        let mut speed = Speed::Hz(500.0);
+        let mut vsync = false;
+        let mut scale = 10;
+        let mut scaling = Scaling::Fit;
+        let mut fullscreen = false;
+        let mut palette = Palette::default();
+        let mut phosphor = 0.0;
+        let mut blend = 0.0;
+        let mut smoothing = Smoothing::None;
+        let mut overlay = Overlay::None;
+        let mut sys = false;
+        let mut overrides: Vec<(String, String)> = Vec::new();
+        let mut beeper = Beeper::new();
+        let mut rewind_seconds = 60;
+        let mut seed = None;
+        let mut rng_mode = RngMode::SplitMix;
+        let mut record = None;
+        let mut play = None;
+        let mut config = None;
+        let mut keymap = None;
+        let mut keys = Vec::new();
+        let mut pad = None;
+        let mut buttons = Vec::new();
+        let mut rumble = false;
+
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--debug" => debug = true,
+                "--trace" => trace = true,
+                "--profile" => {
+                    let name = iter.next().ok_or("--profile needs a name")?;
+                    let profile =
+                        Profile::from_name(name).ok_or(format!("unknown profile: {}", name))?;
+                    quirks = Quirks::new(profile);
+                    profile_depth = profile.stack_depth();
+                }
+                "--stack" => {
+                    let depth = iter.next().ok_or("--stack needs a depth")?;
+                    stack_depth = Some(match depth.as_str() {
+                        "unlimited" | "none" => None,
+                        _ => Some(
+                            depth
+                                .parse::<usize>()
+                                .ok()
+                                .filter(|depth| *depth > 0)
+                                .ok_or(format!("bad stack depth: {}", depth))?,
+                        ),
+                    });
+                }
+                "--quirk" => {
+                    let quirk = iter.next().ok_or("--quirk needs name=value")?;
+                    let (name, value) = quirk
+                        .split_once('=')
+                        .ok_or(format!("expected name=value, got {}", quirk))?;
+                    overrides.push((name.to_string(), value.to_string()));
+                }
+                "--layout" => {
+                    let name = iter.next().ok_or("--layout needs a name")?;
+                    layout = Layout::from_name(name).ok_or(format!("unknown layout: {}", name))?;
+                }
+                "--ipf" => {
+                    let count = iter.next().ok_or("--ipf needs a number")?;
+                    speed = Speed::PerFrame(
+                        count
+                            .parse()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or(format!("bad instructions per frame: {}", count))?,
+                    );
+                }
+                "--hz" => {
+                    let hz = iter.next().ok_or("--hz needs a frequency")?;
+                    speed = Speed::Hz(
+                        hz.parse::<f32>()
+                            .ok()
+                            .filter(|hz| *hz > 0.0)
+                            .ok_or(format!("bad clock speed: {}", hz))?,
+                    );
+                }
+                "--vsync" => vsync = true,
+                "--scale" => {
+                    let value = iter.next().ok_or("--scale needs a number")?;
+                    scale = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|scale| (1..=64).contains(scale))
+                        .ok_or(format!("bad scale: {}", value))?;
+                }
+                "--scaling" => {
+                    let name = iter.next().ok_or("--scaling needs a mode")?;
+                    scaling =
+                        Scaling::from_name(name).ok_or(format!("unknown scaling: {}", name))?;
+                }
+                "--fullscreen" => fullscreen = true,
+                "--palette" => {
+                    palette = Palette::parse(iter.next().ok_or("--palette needs a name")?)?;
+                }
+                "--phosphor" => {
+                    let value = iter.next().ok_or("--phosphor needs a strength")?;
+                    let strength = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|strength| *strength < 100)
+                        .ok_or(format!("bad phosphor strength: {}", value))?;
+                    phosphor = strength as f32 / 100.0;
+                }
+                "--blend" => {
+                    let value = iter.next().ok_or("--blend needs a strength")?;
+                    let strength = value
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|strength| *strength <= 100)
+                        .ok_or(format!("bad blend strength: {}", value))?;
+                    blend = strength as f32 / 100.0;
+                }
+                "--smooth" => {
+                    let name = iter.next().ok_or("--smooth needs a filter")?;
+                    smoothing =
+                        Smoothing::from_name(name).ok_or(format!("unknown smoothing: {}", name))?;
+                }
+                "--overlay" => {
+                    let name = iter.next().ok_or("--overlay needs a name")?;
+                    overlay =
+                        Overlay::from_name(name).ok_or(format!("unknown overlay: {}", name))?;
+                }
+                "--bad-op" => {
+                    let name = iter.next().ok_or("--bad-op needs a policy")?;
+                    bad_op = BadOpPolicy::from_name(name)
+                        .ok_or(format!("unknown bad opcode policy: {}", name))?;
+                }
+                "--sys" => sys = true,
+                "--wave" => {
+                    let name = iter.next().ok_or("--wave needs a name")?;
+                    beeper.waveform =
+                        Waveform::from_name(name).ok_or(format!("unknown waveform: {}", name))?;
+                }
+                "--tone" => {
+                    let hz = iter.next().ok_or("--tone needs a frequency")?;
+                    beeper.freq = hz
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|hz| *hz > 0.0)
+                        .ok_or(format!("bad tone: {}", hz))?;
+                }
+                "--volume" => {
+                    let volume = iter.next().ok_or("--volume needs a value")?;
+                    let volume = volume
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|volume| *volume <= 100)
+                        .ok_or(format!("bad volume: {}", volume))?;
+                    beeper.volume = volume as f32 / 100.0;
+                }
+                "--mute" => beeper.muted = true,
+                "--rewind" => {
+                    let seconds = iter.next().ok_or("--rewind needs a number of seconds")?;
+                    rewind_seconds = seconds
+                        .parse()
+                        .map_err(|_| format!("bad rewind length: {}", seconds))?;
+                }
+                "--seed" => {
+                    let value = iter.next().ok_or("--seed needs a number")?;
+                    seed = Some(value.parse().map_err(|_| format!("bad seed: {}", value))?);
+                }
+                "--rng" => {
+                    let name = iter.next().ok_or("--rng needs a name")?;
+                    rng_mode = RngMode::from_name(name).ok_or(format!("unknown rng: {}", name))?;
+                }
+                "--record" => record = Some(iter.next().ok_or("--record needs a file")?.clone()),
+                "--play" => play = Some(iter.next().ok_or("--play needs a file")?.clone()),
+                "--config" => config = Some(iter.next().ok_or("--config needs a file")?.clone()),
+                "--keymap" => keymap = Some(iter.next().ok_or("--keymap needs a name")?.clone()),
+                "--key" => {
+                    let bind = iter.next().ok_or("--key needs key=binding")?;
+                    let (key, binding) = bind
+                        .split_once('=')
+                        .ok_or(format!("expected key=binding, got {}", bind))?;
+                    let key = u8::from_str_radix(key.trim(), 16)
+                        .ok()
+                        .filter(|key| *key < 16)
+                        .ok_or(format!("bad chip8 key: {}", key))?;
+                    keys.push((key, binding.to_string()));
+                }
+                "--pad" => pad = Some(iter.next().ok_or("--pad needs a name")?.clone()),
+                "--button" => {
+                    let bind = iter.next().ok_or("--button needs button=key")?;
+                    let (button, key) = bind
+                        .split_once('=')
+                        .ok_or(format!("expected button=key, got {}", bind))?;
+                    let key = u8::from_str_radix(key.trim(), 16)
+                        .ok()
+                        .filter(|key| *key < 16)
+                        .ok_or(format!("bad chip8 key: {}", key))?;
+                    buttons.push((button.to_string(), key));
+                }
+                "--rumble" => rumble = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+                _ => {
+                    if rom.is_none() {
+                        rom = Some(arg.clone());
+                    } else {
+                        debug = true;
+                    }
+                }
+            }
+        }
+
+        //single quirks are applied on top of the profile no matter which order they were given in
+        for (name, value) in overrides {
+            quirks.set(&name, &value)?;
+        }
+
+        Ok(Args {
+            rom: rom.ok_or("must pass a rom filename!")?,
+            debug,
+            trace,
+            quirks,
+            stack_depth: stack_depth.unwrap_or(Some(profile_depth)),
+            layout,
+            bad_op,
+            speed,
+            vsync,
+            scale,
+            scaling,
+            fullscreen,
+            palette,
+            phosphor,
+            blend,
+            smoothing,
+            overlay,
+            sys,
+            beeper,
+            rewind_seconds,
+            seed,
+            rng_mode,
+            record,
+            play,
+            config,
+            keymap,
+            keys,
+            pad,
+            buttons,
+            rumble,
+        })
+    }
+}
+//-------------------------------------------------------------------------------------------------
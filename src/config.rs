@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::Path;
+
+//read from the working directory when --config does not name another file
+pub const DEFAULT_PATH: &str = "rust-8.cfg";
+
+//------------------------------------------Config file--------------------------------------------
+//a config file holds command line options so they do not have to be typed every time:
+//
+//  # comments take up a whole line, values can have # in them
+//  profile = schip
+//  # name.sub = value is --name sub=value
+//  quirk.clip = off
+//  # flags are on/off
+//  mute = on
+//
+//  # sections only apply to roms with that file name
+//  [pong.ch8]
+//  keymap = numpad
+//
+//options from the file go in front of the real command line, so the command line still wins
+pub struct Config {
+    //(section, name, value), section is empty for the top of the file
+    entries: Vec<(String, String, String)>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut entries = Vec::new();
+        let mut section = String::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_lowercase();
+                continue;
+            }
+            let (name, value) = line
+                .split_once('=')
+                .ok_or(format!("config line {}: expected name = value", number + 1))?;
+            entries.push((
+                section.clone(),
+                name.trim().to_lowercase(),
+                value.trim().to_string(),
+            ));
+        }
+
+        Ok(Config { entries })
+    }
+
+    //a missing default config is fine, a missing one that was asked for is not
+    pub fn load(path: Option<&str>) -> Result<Config, String> {
+        let text = match path {
+            Some(path) => {
+                fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?
+            }
+            None => fs::read_to_string(DEFAULT_PATH).unwrap_or_default(),
+        };
+        Config::parse(&text)
+    }
+
+    //the file as command line options for this rom: the top of the file, then its own section.
+    //names that are neither flags nor options are refused, passed on their value would be taken
+    //for the rom
+    pub fn args_for(
+        &self,
+        rom: &str,
+        flags: &[&str],
+        options: &[&str],
+    ) -> Result<Vec<String>, String> {
+        let rom = Path::new(rom)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let global = self
+            .entries
+            .iter()
+            .filter(|(section, _, _)| section.is_empty());
+        let own = self
+            .entries
+            .iter()
+            .filter(|(section, _, _)| *section == rom);
+
+        let mut args = Vec::new();
+        for (_, name, value) in global.chain(own) {
+            if flags.contains(&name.as_str()) {
+                if matches!(
+                    value.to_lowercase().as_str(),
+                    "on" | "true" | "1" | "yes" | ""
+                ) {
+                    args.push(format!("--{}", name));
+                }
+                continue;
+            }
+            let option = name.split_once('.').map_or(name.as_str(), |(name, _)| name);
+            if !options.contains(&option) {
+                return Err(format!("unknown option in config: {}", name));
+            }
+            match name.split_once('.') {
+                Some((name, sub)) => {
+                    args.push(format!("--{}", name));
+                    args.push(format!("{}={}", sub, value));
+                }
+                None => {
+                    args.push(format!("--{}", name));
+                    args.push(value.clone());
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+//-------------------------------------------------------------------------------------------------
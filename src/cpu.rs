@@ -1,29 +1,39 @@
-use rand::Rng;
-use std::time::Duration;
-use std::time::Instant;
-
 //component imports
 use crate::display::Display;
-use crate::display::Sprite;
+use crate::error::EmuError;
 use crate::input::Input;
 use crate::memory::Memory;
+use crate::memory::BIG_FONT_BASE;
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::scheduler::Scheduler;
 use crate::sound::Sound;
+use crate::sys::SysCalls;
+
+//per instruction debug output, only printed while cpu.trace is on
+macro_rules! trace {
+    ($cpu:expr, $($arg:tt)*) => {
+        if $cpu.trace {
+            println!($($arg)*);
+        }
+    };
+}
 //-----------------------------------------------CPU-----------------------------------------------
+//an FX0A in progress
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyWait {
+    //register the key ends up in
+    pub reg: u8,
+    //key that went down since the wait started, the wait ends when it comes back up
+    pub key: Option<u8>,
+}
+
 pub struct CPU {
     //PC
     pub pc: u16,
 
-    //DEPRECATED
-    //clock freq in Hz
-    //note: we are aiming for 700 instructions per second
-    //which works out to 0.0014 sec(1.4ms) per instruction.
-    //so the f/d/e cycle should pause at the end to hit this goal if we are not yet longer
-    //freq: i32,
-
-    //clock freq in Hz, along with an instant representing the time of the last clock cycle
-    //we can fence the cpu to this freq by simply checking the elapsed time every cycle
-    pub freq: f32,
-    pub lcc: Instant,
+    //how many instructions make up a 60Hz frame, and how far into the current one we are
+    pub scheduler: Scheduler,
 
     //Memory
     pub mem: Memory,
@@ -37,41 +47,111 @@ pub struct CPU {
     //Input
     pub input: Input,
 
-    //TODO:  is there a better way to do this?
-    pub crashed: bool,
+    //which interpretation of the ambiguous opcodes to use
+    pub quirks: Quirks,
+    //set by the frontend on every 60Hz tick, consumed by DXYN when the display_wait quirk is on
+    pub vblank: bool,
+
+    //program ran 00FD and wants to stop
+    pub exited: bool,
+    //set while FX0A is holding the pc waiting for a key
+    pub key_wait: Option<KeyWait>,
+
+    //ROM::hash of the loaded program, save states only load onto the same rom
+    pub rom_hash: u64,
+
+    //CXNN source, seeded so runs can be replayed
+    pub rng: Rng,
+
+    //native stand-ins for 0NNN machine code routines, empty unless the frontend registers some
+    pub sys: SysCalls,
+
+    //print every instruction as it runs, off so the core stays quiet when embedded
+    pub trace: bool,
 }
 
 impl CPU {
     //cpu initialization
-    //sets PC at beginning of ROM, and sets our freq
+    //sets PC at beginning of ROM
     pub fn new(mem: Memory, disp: Display, sound: Sound, input: Input) -> CPU {
         CPU {
             pc: 512,
-            //500Hz
-            freq: 500.0,
-            //DEBUG freq 50 Hz for testing
-            //freq: 60.0,
-            lcc: Instant::now(),
-            mem: mem,
-            disp: disp,
-            sound: sound,
-            input: input,
-            crashed: false,
+            scheduler: Scheduler::default(),
+            mem,
+            disp,
+            sound,
+            input,
+            quirks: Quirks::default(),
+            vblank: false,
+            exited: false,
+            key_wait: None,
+            rom_hash: 0,
+            rng: Rng::default(),
+            sys: SysCalls::new(),
+            trace: false,
         }
     }
 
+    //called by the scheduler at the start of every 60Hz frame
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+    }
+
+    //run one instruction. any error leaves pc on the faulting instruction
+    pub fn step(&mut self) -> Result<(), EmuError> {
+        let op = self.fetch()?;
+        let regs = self.mem.load_vip_regs();
+        let result = self.decode_and_execute(op);
+        self.mem.store_vip_regs(regs);
+        result
+    }
+
     //get instruction at PC
-    pub fn fetch(&mut self) -> u16 {
+    pub fn fetch(&self) -> Result<u16, EmuError> {
         //println!("fetching opcode at {:#X}",self.PC);
-        //instructions are two bytes long, highest byte first in memory
-        ((self.mem.mem[(self.pc) as usize] as u16) << 8)
-            | (self.mem.mem[(self.pc + 1) as usize] as u16)
+        self.word_at(self.pc)
+    }
+
+    //instructions are two bytes long, highest byte first in memory
+    fn word_at(&self, addr: u16) -> Result<u16, EmuError> {
+        let bytes = self.mem_range(addr as usize, 2)?;
+        Ok((self.mem.mem[bytes.start] as u16) << 8 | self.mem.mem[bytes.start + 1] as u16)
+    }
+
+    //bounds check len bytes of memory starting at addr, on behalf of the instruction at pc
+    fn mem_range(&self, addr: usize, len: usize) -> Result<std::ops::Range<usize>, EmuError> {
+        if addr + len > self.mem.mem.len() {
+            return Err(EmuError::OutOfBounds {
+                addr: self.pc,
+                target: addr,
+                len,
+            });
+        }
+        Ok(addr..addr + len)
     }
-    //decode instruction, return fn pointer
-    pub fn decode_and_execute(&mut self, op: u16) {
+
+    //skip the next instruction, which is 4 bytes long if it is xo-chip's F000 NNNN
+    fn skip(&mut self) {
+        let next = self.word_at(self.pc.wrapping_add(2)).unwrap_or(0);
+        if next == 0xF000 {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
+    fn bad_op(&self, op: u16) -> EmuError {
+        trace!(self, "BAD OPCODE. {:#x} !!THIS IS BAD!! BAD OPCODE.", op);
+        EmuError::BadOpcode { addr: self.pc, op }
+    }
+    //decode instruction and run it
+    pub fn decode_and_execute(&mut self, op: u16) -> Result<(), EmuError> {
         //DEBUG
         //println!("decoding: {:#X}",op);
-        print!("{:#x} ", op);
+        if self.trace {
+            print!("{:#x} ", op);
+        }
+        self.rng.clock();
 
         //first split the func into 4 nibbles
         let n1: u16 = (op & 0b1111000000000000) >> 12;
@@ -86,21 +166,49 @@ impl CPU {
             0x0 => {
                 if n2 == 0x0 && n3 == 0xE && n4 == 0x0 {
                     CPU::cls(self);
-                    return;
+                    return Ok(());
                 }
                 if n2 == 0x0 && n3 == 0xE && n4 == 0xE {
-                    CPU::ret(self);
-                    return;
+                    CPU::ret(self)?;
+                    return Ok(());
                 }
-                CPU::bad_op(self, op);
-                self.crashed = true;
-                return;
+                //superchip
+                if n2 == 0x0 && n3 == 0xC {
+                    CPU::scd(self, n4);
+                    return Ok(());
+                }
+                //xo-chip
+                if n2 == 0x0 && n3 == 0xD {
+                    CPU::scu(self, n4);
+                    return Ok(());
+                }
+                if n2 == 0x0 && n3 == 0xF {
+                    match n4 {
+                        0xB => CPU::scr(self),
+                        0xC => CPU::scl(self),
+                        0xD => CPU::exit(self),
+                        0xE => CPU::low(self),
+                        0xF => CPU::high(self),
+                        _ => {
+                            return Err(self.bad_op(op));
+                        }
+                    }
+                    return Ok(());
+                }
+                CPU::sys(self, op)?;
             }
             0x1 => CPU::jp(self, n2 << 8 | n3 << 4 | n4),
-            0x2 => CPU::call(self, n2 << 8 | n3 << 4 | n4),
+            0x2 => CPU::call(self, n2 << 8 | n3 << 4 | n4)?,
             0x3 => CPU::sei(self, n2, n3 << 4 | n4),
             0x4 => CPU::snei(self, n2, n3 << 4 | n4),
-            0x5 => CPU::ser(self, n2, n3),
+            0x5 => match n4 {
+                0x0 => CPU::ser(self, n2, n3),
+                0x2 => CPU::strange(self, n2, n3)?,
+                0x3 => CPU::ldrange(self, n2, n3)?,
+                _ => {
+                    return Err(self.bad_op(op));
+                }
+            },
             0x6 => CPU::ldi(self, n2, n3 << 4 | n4),
             0x7 => CPU::addi(self, n2, n3 << 4 | n4),
             0x8 => match n4 {
@@ -114,200 +222,317 @@ impl CPU {
                 0x7 => CPU::subn(self, n2, n3),
                 0xE => CPU::shl(self, n2, n3),
                 _ => {
-                    CPU::bad_op(self, op);
-                    self.crashed = true;
+                    return Err(self.bad_op(op));
                 }
             },
             0x9 => CPU::sner(self, n2, n3),
             0xA => CPU::ldireg(self, n2 << 8 | n3 << 4 | n4),
             0xB => CPU::jpv(self, n2 << 8 | n3 << 4 | n4),
             0xC => CPU::rnd(self, n2, n3 << 4 | n4),
-            0xD => CPU::drw(self, n2, n3, n4),
+            0xD => CPU::drw(self, n2, n3, n4)?,
             0xE => {
                 if n3 == 0x9 && n4 == 0xE {
                     CPU::skp(self, n2);
-                    return;
+                    return Ok(());
                 }
                 if n3 == 0xA && n4 == 0x1 {
                     CPU::sknp(self, n2);
-                    return;
+                    return Ok(());
                 }
-                CPU::bad_op(self, op);
-                self.crashed = true;
+                return Err(self.bad_op(op));
             }
             0xF => {
                 let lower_byte = n3 << 4 | n4;
 
+                //F000 NNNN
+                if op == 0xF000 {
+                    CPU::ldilong(self)?;
+                    return Ok(());
+                }
+                //F002
+                if op == 0xF002 {
+                    CPU::ldpattern(self)?;
+                    return Ok(());
+                }
+
                 match lower_byte {
+                    0x01 => CPU::plane(self, n2),
                     0x07 => CPU::ldd(self, n2),
                     0x0A => CPU::ldk(self, n2),
                     0x15 => CPU::sd(self, n2),
                     0x18 => CPU::ss(self, n2),
                     0x1E => CPU::addireg(self, n2),
                     0x29 => CPU::ldsprite(self, n2),
-                    0x33 => CPU::bcd(self, n2),
-                    0x55 => CPU::stseq(self, n2),
-                    0x65 => CPU::ldseq(self, n2),
+                    0x30 => CPU::ldbigsprite(self, n2),
+                    0x33 => CPU::bcd(self, n2)?,
+                    0x3A => CPU::pitch(self, n2),
+                    0x55 => CPU::stseq(self, n2)?,
+                    0x65 => CPU::ldseq(self, n2)?,
+                    0x75 => CPU::strpl(self, n2),
+                    0x85 => CPU::ldrpl(self, n2),
                     _ => {
-                        CPU::bad_op(self, op);
-                        self.crashed = true;
+                        return Err(self.bad_op(op));
                     }
                 };
             }
 
             _ => {
-                CPU::bad_op(self, op);
-                self.crashed = true;
+                return Err(self.bad_op(op));
             }
         }
+        Ok(())
     }
 
     //----------------opcode funcs-------------------
     /*fn test_op(&mut self) {
-        println!("opcode called");
-        println!("cpu PC: {:#X}", self.pc);
+        trace!(self, "opcode called");
+        trace!(self, "cpu PC: {:#X}", self.pc);
     }
     fn deprecated_op(&mut self) {
-        println!("this opcode is deprecated and im lazy so i didnt implement it");
+        trace!(self, "this opcode is deprecated and im lazy so i didnt implement it");
     }*/
-    fn bad_op(&mut self, op: u16) {
-        println!("BAD OPCODE. {:#x} !!THIS IS BAD!! BAD OPCODE.", op);
-    }
     //00E0
     fn cls(&mut self) {
-        println!("clear screen");
+        trace!(self, "clear screen");
         self.disp.clear_disp();
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //00EE
-    fn ret(&mut self) {
-        println!("return");
+    fn ret(&mut self) -> Result<(), EmuError> {
+        trace!(self, "return");
 
-        self.pc = self.mem.stack[self.mem.sp as usize];
-        //Sprintln!("returning to {:#x}",self.mem.stack[self.mem.SP as usize]);
-        self.mem.sp -= 1;
+        self.pc = self
+            .mem
+            .pop_return()
+            .ok_or(EmuError::StackUnderflow { addr: self.pc })?;
+        Ok(())
+    }
+    //00CN
+    fn scd(&mut self, rows: u16) {
+        trace!(self, "scroll down {} rows", rows);
+        self.disp.scroll_down(rows as usize);
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //00DN
+    fn scu(&mut self, rows: u16) {
+        trace!(self, "scroll up {} rows", rows);
+        self.disp.scroll_up(rows as usize);
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //00FB
+    fn scr(&mut self) {
+        trace!(self, "scroll right 4");
+        self.disp.scroll_right();
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //00FC
+    fn scl(&mut self) {
+        trace!(self, "scroll left 4");
+        self.disp.scroll_left();
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //00FD
+    fn exit(&mut self) {
+        trace!(self, "exit interpreter");
+        self.exited = true;
+    }
+    //00FE
+    fn low(&mut self) {
+        trace!(self, "lores mode");
+        self.disp.set_hires(false);
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //00FF
+    fn high(&mut self) {
+        trace!(self, "hires mode");
+        self.disp.set_hires(true);
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //0NNN
+    fn sys(&mut self, op: u16) -> Result<(), EmuError> {
+        let addr = op & 0xFFF;
+        trace!(self, "SYS {:#05x}", addr);
+        let handler = self.sys.get(addr).ok_or_else(|| self.bad_op(op))?;
+        handler(self)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
     //1NNN
     fn jp(&mut self, addr: u16) {
-        println!("JP to addr: {:x}", addr);
+        trace!(self, "JP to addr: {:x}", addr);
         self.pc = addr;
     }
     //2NNN
-    fn call(&mut self, addr: u16) {
-        println!("CALL: {:#X}", addr);
-        self.pc += 2;
-
-        self.mem.sp += 1;
-        self.mem.stack[self.mem.sp as usize] = self.pc;
-        //println!("stack {:#x} is now {:#x}",self.mem.SP as usize,self.pc);
+    fn call(&mut self, addr: u16) -> Result<(), EmuError> {
+        trace!(self, "CALL: {:#X}", addr);
+        if !self.mem.push_return(self.pc.wrapping_add(2)) {
+            return Err(EmuError::StackOverflow {
+                addr: self.pc,
+                chain: self.mem.call_chain(),
+            });
+        }
         self.pc = addr;
+        Ok(())
     }
     //3XNN
     fn sei(&mut self, reg: u16, imm: u16) {
-        println!("Skip if V{:#x} Equal Lit {:#x}", reg, imm);
+        trace!(self, "Skip if V{:#x} Equal Lit {:#x}", reg, imm);
         //let bytes = imm.to_be_bytes();
         //if self.mem.v_regs[reg as usize] == bytes[1]
         if self.mem.v_regs[reg as usize] == (imm as u8) {
-            self.pc += 2;
+            self.skip();
         }
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //4XNN
     fn snei(&mut self, reg: u16, imm: u16) {
-        println!("Skip if V{:#x} Equal Lit {:#x}", reg, imm);
+        trace!(self, "Skip if V{:#x} Equal Lit {:#x}", reg, imm);
 
         if self.mem.v_regs[reg as usize] != (imm as u8) {
-            self.pc += 2;
+            self.skip();
         }
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //5XY0
     fn ser(&mut self, reg1: u16, reg2: u16) {
-        println!("Skip if V{:x} = V{:x}", reg1, reg2);
+        trace!(self, "Skip if V{:x} = V{:x}", reg1, reg2);
 
         if self.mem.v_regs[reg1 as usize] == self.mem.v_regs[reg2 as usize] {
-            self.pc += 2;
+            self.skip();
+        }
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //5XY2
+    fn strange(&mut self, vx: u16, vy: u16) -> Result<(), EmuError> {
+        trace!(self, "Store V{:x} through V{:x} at I", vx, vy);
+
+        let base = self
+            .mem_range(
+                self.mem.I as usize,
+                vx.max(vy) as usize - vx.min(vy) as usize + 1,
+            )?
+            .start;
+        for (offset, reg) in CPU::reg_range(vx, vy).enumerate() {
+            self.mem.mem[base + offset] = self.mem.v_regs[reg];
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+    //5XY3
+    fn ldrange(&mut self, vx: u16, vy: u16) -> Result<(), EmuError> {
+        trace!(self, "Read V{:x} through V{:x} from I", vx, vy);
+
+        let base = self
+            .mem_range(
+                self.mem.I as usize,
+                vx.max(vy) as usize - vx.min(vy) as usize + 1,
+            )?
+            .start;
+        for (offset, reg) in CPU::reg_range(vx, vy).enumerate() {
+            self.mem.v_regs[reg] = self.mem.mem[base + offset];
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+    //registers X to Y inclusive, walking backwards when X > Y
+    fn reg_range(vx: u16, vy: u16) -> Box<dyn Iterator<Item = usize>> {
+        let (vx, vy) = (vx as usize, vy as usize);
+        if vx <= vy {
+            Box::new(vx..=vy)
+        } else {
+            Box::new((vy..=vx).rev())
         }
-        self.pc += 2;
     }
     //6XNN
     fn ldi(&mut self, reg: u16, byte: u16) {
-        println!("Load imm: {:#x} into reg {:#x}", byte, reg);
+        trace!(self, "Load imm: {:#x} into reg {:#x}", byte, reg);
         self.mem.v_regs[reg as usize] = byte.to_be_bytes()[1];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //7XNN
     fn addi(&mut self, reg: u16, byte: u16) {
-        println!("Add imm: {:#x} into reg {:#x}", byte, reg);
+        trace!(self, "Add imm: {:#x} into reg {:#x}", byte, reg);
         let bytes = byte.to_be_bytes();
         //let sum: u16 = (self.mem.v_regs[reg as usize] as u16 + bytes[1] as u16) & 0x00FF;
         //self.mem.v_regs[reg as usize] = sum.to_be_bytes()[1];
         self.mem.v_regs[reg as usize] = self.mem.v_regs[reg as usize].wrapping_add(bytes[1]);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY0
     fn ldr(&mut self, vx: u16, vy: u16) {
-        println!("Set V{:x} = V{:x}", vy, vx);
+        trace!(self, "Set V{:x} = V{:x}", vy, vx);
         self.mem.v_regs[vx as usize] = self.mem.v_regs[vy as usize];
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY1
     fn or(&mut self, vx: u16, vy: u16) {
-        println!("V{:x} = V{:x} | V{:x}", vx, vx, vy);
+        trace!(self, "V{:x} = V{:x} | V{:x}", vx, vx, vy);
         self.mem.v_regs[vx as usize] |= self.mem.v_regs[vy as usize];
-        self.pc += 2;
+        if self.quirks.vf_reset {
+            self.mem.v_regs[0xF] = 0;
+        }
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY2
     fn and(&mut self, vx: u16, vy: u16) {
-        println!("V{:x} = V{:x} & V{:x}", vx, vx, vy);
+        trace!(self, "V{:x} = V{:x} & V{:x}", vx, vx, vy);
         self.mem.v_regs[vx as usize] &= self.mem.v_regs[vy as usize];
-        self.pc += 2;
+        if self.quirks.vf_reset {
+            self.mem.v_regs[0xF] = 0;
+        }
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY3
     fn xor(&mut self, vx: u16, vy: u16) {
-        println!("V{:x} = V{:x} ^ V{:x}", vx, vx, vy);
+        trace!(self, "V{:x} = V{:x} ^ V{:x}", vx, vx, vy);
         self.mem.v_regs[vx as usize] ^= self.mem.v_regs[vy as usize];
-        self.pc += 2;
+        if self.quirks.vf_reset {
+            self.mem.v_regs[0xF] = 0;
+        }
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY4
     fn addr(&mut self, vx: u16, vy: u16) {
-        println!("V{:x} = V{:x} + V{:x}", vx, vx, vy);
+        trace!(self, "V{:x} = V{:x} + V{:x}", vx, vx, vy);
         if ((self.mem.v_regs[vx as usize] as i32) + (self.mem.v_regs[vy as usize] as i32)) > 255 {
-            println!("overflow! setting vf = 1");
+            trace!(self, "overflow! setting vf = 1");
             self.mem.v_regs[0xF] = 1;
         }
         self.mem.v_regs[vx as usize] =
             self.mem.v_regs[vx as usize].wrapping_add(self.mem.v_regs[vy as usize]);
         self.mem.v_regs[0xf] = 0;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY5
     //TODO: THIS MIGHT BE BUGGED FOR NEGATIVE NUMBERS
     fn subr(&mut self, vx: u16, vy: u16) {
-        println!("check V{:x} = V{:x} - V{:x} for overflow", vx, vx, vy);
+        trace!(self, "check V{:x} = V{:x} - V{:x} for overflow", vx, vx, vy);
         if (self.mem.v_regs[vy as usize] as i32) > (self.mem.v_regs[vx as usize] as i32) {
-            println!("underflow! setting vf = 0");
+            trace!(self, "underflow! setting vf = 0");
             self.mem.v_regs[0xF] = 0;
         } else {
             self.mem.v_regs[0xF] = 1;
         }
         self.mem.v_regs[vx as usize] =
             self.mem.v_regs[vx as usize].wrapping_sub(self.mem.v_regs[vy as usize]);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY6
     fn shr(&mut self, vx: u16, vy: u16) {
-        println!("Shift V{:x} Right 1 and store in V{:x}", vy, vx);
-        let lsb = self.mem.v_regs[vy as usize] & 0x1;
+        trace!(self, "Shift V{:x} Right 1 and store in V{:x}", vy, vx);
+        let src = if self.quirks.shift_vy { vy } else { vx };
+        let lsb = self.mem.v_regs[src as usize] & 0x1;
         self.mem.v_regs[0xF] = lsb;
-        self.mem.v_regs[vx as usize] = self.mem.v_regs[vy as usize] >> 1;
-        self.pc += 2;
+        self.mem.v_regs[vx as usize] = self.mem.v_regs[src as usize] >> 1;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XY7
     fn subn(&mut self, vx: u16, vy: u16) {
-        println!("V{:x} = V{:x} - V{:x}", vx, vy, vx);
+        trace!(self, "V{:x} = V{:x} - V{:x}", vx, vy, vx);
         if (self.mem.v_regs[vx as usize] as i32) > (self.mem.v_regs[vy as usize] as i32) {
-            println!("underflow! setting vf = 0");
+            trace!(self, "underflow! setting vf = 0");
             self.mem.v_regs[0xF] = 0;
         } else {
             self.mem.v_regs[0xF] = 1;
@@ -317,191 +542,624 @@ impl CPU {
         % 255) as u8;*/
         self.mem.v_regs[vx as usize] =
             self.mem.v_regs[vy as usize].wrapping_sub(self.mem.v_regs[vx as usize]);
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //8XYE
     fn shl(&mut self, vx: u16, vy: u16) {
-        println!("Shift V{:x} Left 1 and store in V{:x}", vy, vx);
+        trace!(self, "Shift V{:x} Left 1 and store in V{:x}", vy, vx);
         /*println!(
             "V{:x} is {:8b} \nV{:x} is {:8b}",
             vy, self.mem.v_regs[vy as usize], vx, self.mem.v_regs[vx as usize]
         );*/
-        let msb = self.mem.v_regs[vy as usize] & 0b10000000;
+        let src = if self.quirks.shift_vy { vy } else { vx };
+        let msb = self.mem.v_regs[src as usize] & 0b10000000;
         self.mem.v_regs[0xf] = msb;
-        self.mem.v_regs[vx as usize] = self.mem.v_regs[vy as usize] << 1;
-        self.pc += 2;
+        self.mem.v_regs[vx as usize] = self.mem.v_regs[src as usize] << 1;
+        self.pc = self.pc.wrapping_add(2);
     }
     //9XY0
     fn sner(&mut self, vx: u16, vy: u16) {
-        println!("Skip if V{:x} != V{:x}", vx, vy);
+        trace!(self, "Skip if V{:x} != V{:x}", vx, vy);
 
         if self.mem.v_regs[vx as usize] != self.mem.v_regs[vy as usize] {
-            self.pc += 2;
+            self.skip();
         }
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //ANNN
     fn ldireg(&mut self, imm: u16) {
-        println!("load imm {:x} into I register", imm);
+        trace!(self, "load imm {:x} into I register", imm);
         self.mem.I = imm;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
-    //BNNN
+    //BNNN (or BXNN with the jump quirk)
     fn jpv(&mut self, imm: u16) {
-        println!("jump to {:x} + V0", imm);
-        self.pc = self.mem.v_regs[0] as u16 + imm;
+        let reg = if self.quirks.jump_vx { imm >> 8 } else { 0 };
+        trace!(self, "jump to {:x} + V{:x}", imm, reg);
+        self.pc = self.mem.v_regs[reg as usize] as u16 + imm;
     }
     //CXNN
     fn rnd(&mut self, reg: u16, imm: u16) {
-        print!("rand into V{:x} with a mask of {:x} ", reg, imm);
-        let mut rng = rand::thread_rng();
-        let value: u16 = rng.gen_range(0..256);
+        if self.trace {
+            print!("rand into V{:x} with a mask of {:x} ", reg, imm);
+        }
+        let value = self.rng.next_byte() as u16;
 
         self.mem.v_regs[reg as usize] = (value & imm) as u8;
-        println!("val is {:x}", self.mem.v_regs[reg as usize]);
+        trace!(self, "val is {:x}", self.mem.v_regs[reg as usize]);
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //DXYN
-    fn drw(&mut self, vx: u16, vy: u16, length: u16) {
+    fn drw(&mut self, vx: u16, vy: u16, length: u16) -> Result<(), EmuError> {
+        //hold the pc here until the frontend signals the next frame
+        if self.quirks.display_wait && !self.vblank {
+            return Ok(());
+        }
+        self.vblank = false;
+
         let x = self.mem.v_regs[vx as usize];
         let y = self.mem.v_regs[vy as usize];
-        println!(
+        trace!(
+            self,
             "draw! sprite from addr: {:#x}, length: {:#x}, x:{:#x}, y:{:#x}",
-            self.mem.I, length, x, y
+            self.mem.I,
+            length,
+            x,
+            y
         );
 
-        let mut lines: Vec<u8> = Vec::new();
+        //DXY0 is a 16x16 sprite, two bytes per line
+        let wide = length == 0;
+        let length = if wide { 32 } else { length };
 
-        for i in 0..length {
-            let spriteline: u8 = self.mem.mem[(self.mem.I + i) as usize];
-            //println!("spriteline {}, is {:#x}",i,spriteline);
-            lines.push(spriteline);
-        }
+        //xo-chip: each selected plane takes its own copy of the sprite data, plane 1 first
+        let mut addr = self.mem.I as usize;
+        let mut flag = 0;
+        for plane in [1u8, 2u8] {
+            if self.disp.planes & plane == 0 {
+                continue;
+            }
 
-        let sprite = Sprite::new(lines, x.into(), y.into());
-        //this call handles setting vf if a pixel is unset, and vice versa
-        self.disp.push_sprite(sprite, &mut self.mem);
-        self.disp.update_disp();
+            let lines = self.mem_range(addr, length as usize)?;
+            addr += length as usize;
 
-        self.pc += 2;
+            //hires counts rows per plane and the counts of both planes add up
+            flag +=
+                self.disp
+                    .draw_sprite(plane, &self.mem.mem[lines], wide, x, y, self.quirks.clip);
+        }
+        //lores only says whether anything hit, in either plane
+        if !self.disp.hires {
+            flag = flag.min(1);
+        }
+        self.mem.v_regs[0xf] = flag;
+
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
     //EX9E
     fn skp(&mut self, reg: u16) {
-        println!("skip if key {:x} pressed", self.mem.v_regs[reg as usize]);
-        //DEBUG: DUMP KEYSTATES
-        self.input.dump();
+        trace!(
+            self,
+            "skip if key {:x} pressed",
+            self.mem.v_regs[reg as usize]
+        );
+        if self.trace {
+            self.input.dump();
+        }
 
-        if self.input.keys[self.mem.v_regs[reg as usize] as usize] {
-            self.pc += 2;
+        if self.input.keys[(self.mem.v_regs[reg as usize] & 0xF) as usize] {
+            self.skip();
         }
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //EXA1
     fn sknp(&mut self, reg: u16) {
-        println!(
+        trace!(
+            self,
             "skip if key {:x} NOT pressed",
             self.mem.v_regs[reg as usize]
         );
-        //DEBUG: DUMP KEYSTATES
-        self.input.dump();
+        if self.trace {
+            self.input.dump();
+        }
 
-        if !self.input.keys[self.mem.v_regs[reg as usize] as usize] {
-            self.pc += 2;
+        if !self.input.keys[(self.mem.v_regs[reg as usize] & 0xF) as usize] {
+            self.skip();
         }
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //F000 NNNN
+    fn ldilong(&mut self) -> Result<(), EmuError> {
+        let addr = self.word_at(self.pc.wrapping_add(2))?;
+        trace!(self, "load long addr {:x} into I register", addr);
+        self.mem.I = addr;
+        self.pc = self.pc.wrapping_add(4);
+        Ok(())
+    }
+    //F002
+    fn ldpattern(&mut self) -> Result<(), EmuError> {
+        trace!(self, "load audio pattern from I");
+        let bytes = self.mem_range(self.mem.I as usize, 16)?;
+        self.sound.pattern.copy_from_slice(&self.mem.mem[bytes]);
+        self.sound.pattern_loaded = true;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+    //FN01
+    fn plane(&mut self, planes: u16) {
+        trace!(self, "select planes {:b}", planes);
+        self.disp.planes = (planes & 0b11) as u8;
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX07
     fn ldd(&mut self, reg: u16) {
-        println!("set V{:x} = delay timer", reg);
+        trace!(self, "set V{:x} = delay timer", reg);
 
         self.mem.v_regs[reg as usize] = self.sound.dt;
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX0A
     fn ldk(&mut self, reg: u16) {
-        println!("Load Keystroke into vx");
-        //TODO: fix me jesus christ this is beyond hacky
+        //first time round only starts the wait, keys that were already down do not count
+        let mut wait = match self.key_wait {
+            Some(wait) if wait.reg == reg as u8 => wait,
+            _ => {
+                trace!(self, "wait for key into V{:x}", reg);
+                self.input.take_edges();
+                self.key_wait = Some(KeyWait {
+                    reg: reg as u8,
+                    key: None,
+                });
+                return;
+            }
+        };
 
-        //to halt execution until a key is pressed, do not increment pc until a change happens in keys
-        //we approximate this by only updating if key_lu < 0.5 seconds in the past
-        if self.input.lu.elapsed().as_millis() < 5 {
-            self.mem.v_regs[reg as usize] = self.input.last_key;
-            self.pc += 2;
+        //pc stays on this instruction until a key has gone down, and unless the quirk says
+        //otherwise come back up again
+        let (pressed, released) = self.input.take_edges();
+        if wait.key.is_none() && pressed != 0 {
+            wait.key = Some(pressed.trailing_zeros() as u8);
+        }
+        match wait.key {
+            Some(key) if self.quirks.key_press || released & (1 << key) != 0 => {
+                trace!(self, "got key {:x} into V{:x}", key, reg);
+                self.mem.v_regs[reg as usize] = key;
+                self.key_wait = None;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            _ => self.key_wait = Some(wait),
         }
     }
     //FX15
     fn sd(&mut self, reg: u16) {
-        println!("Set delay timer to contents of V{:x}", reg);
+        trace!(self, "Set delay timer to contents of V{:x}", reg);
 
         self.sound.dt = self.mem.v_regs[reg as usize];
-        //self.sound.dt_lu = Instant::now();
-        self.sound.dt_lu = 0;
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX18
     fn ss(&mut self, reg: u16) {
-        println!("Set sound timer to contents of V{:x}", reg);
+        trace!(self, "Set sound timer to contents of V{:x}", reg);
 
         self.sound.st = self.mem.v_regs[reg as usize];
-        //self.sound.st_lu = Instant::now();
-        self.sound.st_lu = 0;
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX1E
     fn addireg(&mut self, reg: u16) {
-        println!("Add V{:x} to I reg", reg);
-        self.mem.I += self.mem.v_regs[reg as usize] as u16;
-        self.pc += 2;
+        trace!(self, "Add V{:x} to I reg", reg);
+        self.mem.I = self
+            .mem
+            .I
+            .wrapping_add(self.mem.v_regs[reg as usize] as u16);
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX29
     fn ldsprite(&mut self, reg: u16) {
-        println!("Set I = location of font sprite V{:x}", reg);
+        trace!(self, "Set I = location of font sprite V{:x}", reg);
 
-        self.mem.I = (5 * self.mem.v_regs[reg as usize]).into();
-        self.pc += 2;
+        self.mem.I = 5 * (self.mem.v_regs[reg as usize] & 0xF) as u16;
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //FX30
+    fn ldbigsprite(&mut self, reg: u16) {
+        trace!(self, "Set I = location of big font sprite V{:x}", reg);
+
+        self.mem.I = BIG_FONT_BASE + 10 * (self.mem.v_regs[reg as usize] & 0xF) as u16;
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX33
-    fn bcd(&mut self, reg: u16) {
-        println!("Store BCD rep of V{:x} at I", reg);
+    fn bcd(&mut self, reg: u16) -> Result<(), EmuError> {
+        trace!(self, "Store BCD rep of V{:x} at I", reg);
+        let base = self.mem_range(self.mem.I as usize, 3)?.start;
         let value: i32 = self.mem.v_regs[reg as usize].into();
         let hundreds: i32 = value / 100;
         let tens: i32 = (value % 100) / 10;
         let ones: i32 = value % 10;
 
-        self.mem.mem[self.mem.I as usize] = hundreds as u8;
-        self.mem.mem[(self.mem.I + 1) as usize] = tens as u8;
-        self.mem.mem[(self.mem.I + 2) as usize] = ones as u8;
+        self.mem.mem[base] = hundreds as u8;
+        self.mem.mem[base + 1] = tens as u8;
+        self.mem.mem[base + 2] = ones as u8;
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+    //FX3A
+    fn pitch(&mut self, reg: u16) {
+        trace!(self, "Set audio pitch to V{:x}", reg);
+        self.sound.pitch = self.mem.v_regs[reg as usize];
+        self.pc = self.pc.wrapping_add(2);
     }
     //FX55
-    fn stseq(&mut self, reg: u16) {
-        println!("Store Vregs 0 through V{:x} at I", reg);
+    fn stseq(&mut self, reg: u16) -> Result<(), EmuError> {
+        trace!(self, "Store Vregs 0 through V{:x} at I", reg);
+        let base = self.mem_range(self.mem.I as usize, reg as usize + 1)?.start;
 
         for i in 0..(reg + 1) {
-            self.mem.mem[(self.mem.I + i) as usize] = self.mem.v_regs[i as usize];
+            self.mem.mem[base + i as usize] = self.mem.v_regs[i as usize];
         }
 
-        self.mem.I += (reg + 1) as u16;
+        if self.quirks.load_store_inc_i {
+            self.mem.I = self.mem.I.wrapping_add(reg + 1);
+        }
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
     }
     //FX65
-    fn ldseq(&mut self, reg: u16) {
-        println!("Read Vregs 0 through V{:x} from I", reg);
+    fn ldseq(&mut self, reg: u16) -> Result<(), EmuError> {
+        trace!(self, "Read Vregs 0 through V{:x} from I", reg);
+        let base = self.mem_range(self.mem.I as usize, reg as usize + 1)?.start;
 
         for i in 0..(reg + 1) {
-            self.mem.v_regs[i as usize] = self.mem.mem[(self.mem.I + i) as usize];
+            self.mem.v_regs[i as usize] = self.mem.mem[base + i as usize];
         }
 
-        self.mem.I += (reg + 1) as u16;
+        if self.quirks.load_store_inc_i {
+            self.mem.I = self.mem.I.wrapping_add(reg + 1);
+        }
 
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+    //FX75
+    fn strpl(&mut self, reg: u16) {
+        trace!(self, "Store Vregs 0 through V{:x} in RPL flags", reg);
+
+        for i in 0..(reg + 1) {
+            self.mem.rpl[i as usize] = self.mem.v_regs[i as usize];
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+    }
+    //FX85
+    fn ldrpl(&mut self, reg: u16) {
+        trace!(self, "Read Vregs 0 through V{:x} from RPL flags", reg);
+
+        for i in 0..(reg + 1) {
+            self.mem.v_regs[i as usize] = self.mem.rpl[i as usize];
+        }
+
+        self.pc = self.pc.wrapping_add(2);
     }
     //-----------------------------------------------
 }
+
+//a machine with empty memory and nothing loaded yet
+impl Default for CPU {
+    fn default() -> CPU {
+        CPU::new(Memory::new(), Display::new(), Sound::new(), Input::new())
+    }
+}
 //-------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Layout, VIP_REGS, VIP_RESERVED, VIP_STACK_TOP};
+    use crate::rng::RngMode;
+    use crate::rom::ROM;
+    use crate::scheduler::Speed;
+
+    //FX0A into V5
+    const WAIT_V5: u16 = 0xF50A;
+
+    #[test]
+    fn key_wait_finishes_on_release() {
+        let mut cpu = CPU::default();
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+
+        cpu.input.press(0x7);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.key_wait.unwrap().key, Some(0x7));
+
+        cpu.input.release(0x7);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.mem.v_regs[5], 0x7);
+        assert_eq!(cpu.key_wait, None);
+    }
+
+    #[test]
+    fn key_wait_ignores_keys_already_held() {
+        let mut cpu = CPU::default();
+        cpu.input.press(0x3);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+
+        //letting go of a key that was down before the wait started does not count
+        cpu.input.release(0x3);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+        assert_eq!(cpu.key_wait.unwrap().key, None);
+
+        cpu.input.press(0x3);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        cpu.input.release(0x3);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.mem.v_regs[5], 0x3);
+    }
+
+    #[test]
+    fn key_wait_only_releases_the_first_key() {
+        let mut cpu = CPU::default();
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+
+        cpu.input.press(0xA);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        cpu.input.press(0xB);
+        cpu.input.release(0xB);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+
+        cpu.input.release(0xA);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.mem.v_regs[5], 0xA);
+    }
+
+    #[test]
+    fn key_wait_finishes_on_press_with_the_quirk() {
+        let mut cpu = CPU::default();
+        cpu.quirks.key_press = true;
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+
+        cpu.input.press(0xE);
+        cpu.decode_and_execute(WAIT_V5).unwrap();
+        assert_eq!(cpu.pc, 0x202);
+        assert_eq!(cpu.mem.v_regs[5], 0xE);
+    }
+    #[test]
+    fn register_ranges_go_both_ways() {
+        let mut cpu = CPU::default();
+        cpu.mem.I = 0x400;
+        cpu.mem.v_regs[2..=5].copy_from_slice(&[1, 2, 3, 4]);
+
+        //5XY2 with X > Y stores them backwards
+        cpu.decode_and_execute(0x5522).unwrap();
+        assert_eq!(cpu.mem.mem[0x400..0x404], [4, 3, 2, 1]);
+        assert_eq!(cpu.mem.I, 0x400);
+
+        //5XY3 reads them into V8-VB in memory order
+        cpu.decode_and_execute(0x58B3).unwrap();
+        assert_eq!(cpu.mem.v_regs[8..=0xB], [4, 3, 2, 1]);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn long_i_load_and_skipping_it() {
+        let mut cpu = CPU::default();
+        cpu.mem.mem[0x200..0x206].copy_from_slice(&[0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.mem.I, 0xBEEF);
+        assert_eq!(cpu.pc, 0x204);
+
+        //a skip lands past both words of F000 NNNN
+        cpu.pc = 0x1FE;
+        cpu.mem.mem[0x1FE..0x200].copy_from_slice(&[0x30, 0x00]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn two_planes_take_separate_sprite_data() {
+        let mut cpu = CPU::default();
+        cpu.mem.I = 0x400;
+        cpu.mem.mem[0x400..0x404].copy_from_slice(&[0xC0, 0xC0, 0x00, 0x80]);
+
+        //FN01 selects both planes, then one DXY2 draws 2 lines into each
+        cpu.decode_and_execute(0xF301).unwrap();
+        assert_eq!(cpu.disp.planes, 3);
+        cpu.decode_and_execute(0xD002).unwrap();
+        assert_eq!(cpu.disp.pixel(0, 0), 1);
+        assert_eq!(cpu.disp.pixel(1, 0), 1);
+        assert_eq!(cpu.disp.pixel(0, 1), 3);
+        assert_eq!(cpu.mem.v_regs[0xF], 0);
+
+        //00DN only scrolls the selected plane
+        cpu.decode_and_execute(0xF201).unwrap();
+        cpu.decode_and_execute(0x00D1).unwrap();
+        assert_eq!(cpu.disp.pixel(0, 0), 3);
+        assert_eq!(cpu.disp.pixel(0, 1), 1);
+        assert_eq!(cpu.disp.pixel(1, 1), 1);
+    }
+
+    #[test]
+    fn hires_collisions_add_up_over_both_planes() {
+        let mut cpu = CPU::default();
+        cpu.disp.set_hires(true);
+        cpu.disp.planes = 3;
+        cpu.mem.I = 0x400;
+        cpu.mem.mem[0x400..0x404].copy_from_slice(&[0xFF, 0xFF, 0x80, 0x80]);
+
+        cpu.decode_and_execute(0xD002).unwrap();
+        assert_eq!(cpu.mem.v_regs[0xF], 0);
+        //2 rows hit in plane 1 and 2 in plane 2
+        cpu.decode_and_execute(0xD002).unwrap();
+        assert_eq!(cpu.mem.v_regs[0xF], 4);
+
+        //in lores both planes hitting is still just 1
+        cpu.disp.set_hires(false);
+        cpu.decode_and_execute(0xD002).unwrap();
+        cpu.decode_and_execute(0xD002).unwrap();
+        assert_eq!(cpu.mem.v_regs[0xF], 1);
+    }
+
+    #[test]
+    fn skipped_bad_opcodes_use_up_the_frame() {
+        let mut cpu = CPU::default();
+        cpu.scheduler.speed = Speed::PerFrame(3);
+
+        //0000 is not an instruction, memory after the rom is all of it
+        let mut skipped = 0;
+        while let Err(EmuError::BadOpcode { .. }) = cpu.run_frame() {
+            cpu.skip_instruction();
+            skipped += 1;
+        }
+        assert_eq!(skipped, 3);
+        assert_eq!(cpu.pc, 0x206);
+        assert_eq!(cpu.scheduler.frame, 1);
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch() {
+        let mut cpu = CPU::default();
+        cpu.mem.I = 0x400;
+        for i in 0..16 {
+            cpu.mem.mem[0x400 + i] = i as u8 * 17;
+        }
+        cpu.decode_and_execute(0xF002).unwrap();
+        assert!(cpu.sound.pattern_loaded);
+        assert_eq!(cpu.sound.pattern[15], 0xFF);
+
+        cpu.mem.v_regs[6] = 100;
+        cpu.decode_and_execute(0xF63A).unwrap();
+        assert_eq!(cpu.sound.pitch, 100);
+        assert_eq!(cpu.pc, 0x204);
+
+        //the pattern has to fit in memory
+        cpu.mem.I = 0xFFF8;
+        assert!(cpu.decode_and_execute(0xF002).is_err());
+    }
+
+    #[test]
+    fn stack_overflow_and_underflow() {
+        let mut cpu = CPU::default();
+        cpu.mem.stack_depth = Some(2);
+        //0x200 calls 0x300, which calls 0x400, which calls one level too deep
+        cpu.mem.mem[0x200..0x202].copy_from_slice(&[0x23, 0x00]);
+        cpu.mem.mem[0x300..0x302].copy_from_slice(&[0x24, 0x00]);
+        cpu.mem.mem[0x400..0x402].copy_from_slice(&[0x25, 0x00]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        match cpu.step() {
+            Err(EmuError::StackOverflow { addr, chain }) => {
+                assert_eq!(addr, 0x400);
+                assert_eq!(chain, vec![0x200, 0x300]);
+            }
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+        assert_eq!(cpu.pc, 0x400);
+
+        let mut cpu = CPU::default();
+        assert!(matches!(
+            cpu.decode_and_execute(0x00EE),
+            Err(EmuError::StackUnderflow { addr: 0x200 })
+        ));
+    }
+
+    #[test]
+    fn vip_layout_keeps_registers_and_stack_in_memory() {
+        let mut cpu = CPU::default();
+        cpu.mem.layout = Layout::Vip;
+        let regs = VIP_REGS as usize;
+
+        //registers are read from memory before an instruction and written back after it
+        cpu.mem.mem[regs + 3] = 7;
+        cpu.mem.mem[0x200..0x204].copy_from_slice(&[0x73, 0x01, 0x23, 0x00]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.mem.v_regs[3], 8);
+        assert_eq!(cpu.mem.mem[regs + 3], 8);
+
+        //the return address lives in memory too, and RET uses whatever is there now
+        cpu.step().unwrap();
+        let slot = VIP_STACK_TOP as usize - 2;
+        assert_eq!(cpu.mem.mem[slot..slot + 2], [0x02, 0x04]);
+        cpu.mem.mem[slot + 1] = 0x10;
+        cpu.decode_and_execute(0x00EE).unwrap();
+        assert_eq!(cpu.pc, 0x210);
+
+        //roms may only fill memory up to the interpreter's area
+        let fits = VIP_RESERVED as usize - 0x200;
+        let mut mem = Memory::new();
+        mem.layout = Layout::Vip;
+        assert!(mem
+            .init(&ROM {
+                buffer: vec![0; fits]
+            })
+            .is_ok());
+        assert!(matches!(
+            mem.init(&ROM {
+                buffer: vec![0; fits + 1]
+            }),
+            Err(EmuError::RomTooLarge { max, .. }) if max == fits
+        ));
+    }
+
+    #[test]
+    fn hz_carries_over_and_timers_tick_once_a_frame() {
+        let mut cpu = CPU::default();
+        //1.5 instructions a frame, each one adds 1 to V0
+        cpu.scheduler.speed = Speed::Hz(90.0);
+        for addr in (0x200..0x300).step_by(2) {
+            cpu.mem.mem[addr..addr + 2].copy_from_slice(&[0x70, 0x01]);
+        }
+        cpu.sound.dt = 10;
+        cpu.sound.st = 2;
+
+        let mut counts = Vec::new();
+        for _ in 0..4 {
+            let before = cpu.mem.v_regs[0];
+            cpu.run_frame().unwrap();
+            counts.push(cpu.mem.v_regs[0] - before);
+        }
+        assert_eq!(counts, vec![1, 2, 1, 2]);
+        assert_eq!(cpu.scheduler.frame, 4);
+        assert_eq!(cpu.sound.dt, 6);
+        assert_eq!(cpu.sound.st, 0);
+    }
+
+    #[test]
+    fn same_seed_same_random_numbers() {
+        //CXFF into V0 forty times, with the counter clocked by the instructions in between
+        fn run(seed: u64, mode: RngMode) -> Vec<u8> {
+            let mut cpu = CPU {
+                rng: Rng::new(seed),
+                ..CPU::default()
+            };
+            cpu.rng.mode = mode;
+            for addr in (0x200..0x250).step_by(2) {
+                cpu.mem.mem[addr..addr + 2].copy_from_slice(&[0xC0, 0xFF]);
+            }
+            (0..40)
+                .map(|_| {
+                    cpu.step().unwrap();
+                    cpu.mem.v_regs[0]
+                })
+                .collect()
+        }
+
+        for mode in [RngMode::SplitMix, RngMode::Counter] {
+            let numbers = run(12345, mode);
+            assert_eq!(numbers, run(12345, mode));
+            assert_ne!(numbers, run(54321, mode));
+            //and not stuck on one value
+            assert!(numbers.iter().any(|n| *n != numbers[0]));
+        }
+    }
+}
@@ -6,9 +6,8 @@ use sdl2::rect::Rect;
 use sdl2::ttf::Sdl2TtfContext;
 use sdl2::Sdl;
 
-use std::time::Instant;
-
-use crate::cpu::CPU;
+use rust_8::cpu::CPU;
+use rust_8::frontend::Video;
 
 pub struct Debugger {
     //pub sdl: Sdl,
@@ -36,14 +35,11 @@ impl Debugger {
         }
     }
 
-    pub fn run(&mut self, pump: &mut sdl2::EventPump, cpu: &mut CPU) {
+    pub fn run(&mut self, pump: &mut sdl2::EventPump, cpu: &mut CPU, video: &mut dyn Video) {
         let mut run: bool = false;
         let font = self
             .ttf
-            .load_font(
-                "./".to_owned()+"src/FiraCode-Regular.ttf",
-                128,
-            )
+            .load_font("./".to_owned() + "src/FiraCode-Regular.ttf", 128)
             .unwrap();
         let texture_creator = self.canvas.texture_creator();
 
@@ -54,7 +50,7 @@ impl Debugger {
             self.canvas.set_draw_color(Color::WHITE);
 
             //draw current pc and instruction
-            let raw_op = cpu.fetch();
+            let raw_op = cpu.fetch().unwrap_or(0);
             let pc_str = format!("{:#04x}: {:04x}", cpu.pc, raw_op);
             let text_surface = font
                 .render(&pc_str)
@@ -123,6 +119,34 @@ impl Debugger {
             self.canvas
                 .copy(&cur_st_texture, None, Some(Rect::new(155, 57, 150, 15)))
                 .unwrap();
+            //frame and instructions left in it
+            let value = format!(
+                "frame: {} left: {}",
+                cpu.scheduler.frame,
+                cpu.scheduler.pending.unwrap_or(0)
+            );
+            let cur_frame_surface = font.render(&value).solid(Color::WHITE).unwrap();
+            let cur_frame_texture = texture_creator
+                .create_texture_from_surface(&cur_frame_surface)
+                .unwrap();
+            self.canvas
+                .copy(&cur_frame_texture, None, Some(Rect::new(155, 72, 150, 15)))
+                .unwrap();
+            //FX0A wait
+            let value = match cpu.key_wait {
+                Some(wait) => match wait.key {
+                    Some(key) => format!("FX0A: V{:x} <- {:x}, until released", wait.reg, key),
+                    None => format!("FX0A: V{:x} <- waiting for key", wait.reg),
+                },
+                None => String::from("FX0A: -"),
+            };
+            let cur_wait_surface = font.render(&value).solid(Color::WHITE).unwrap();
+            let cur_wait_texture = texture_creator
+                .create_texture_from_surface(&cur_wait_surface)
+                .unwrap();
+            self.canvas
+                .copy(&cur_wait_texture, None, Some(Rect::new(155, 87, 250, 15)))
+                .unwrap();
 
             //draw a chunk of memory
             //default base is 0x200
@@ -153,36 +177,42 @@ impl Debugger {
                         keycode: Some(Keycode::Space),
                         ..
                     } => {
-                        //set debugger to dead and update timers before returning to main function
+                        //set debugger to dead before returning to main function
                         self.live = false;
-                        cpu.lcc = Instant::now();
-                        //cpu.sound.dt_lu = Instant::now();
-                        //cpu.sound.st_lu = Instant::now();
-                        cpu.sound.dt_lu += 1;
-                        cpu.sound.st_lu += 1;
                         break 'running;
                     }
                     Event::KeyDown {
                         keycode: Some(Keycode::N),
                         ..
                     } => run = true,
+                    //step over the instruction without running it, to get past a bad opcode
+                    Event::KeyDown {
+                        keycode: Some(Keycode::S),
+                        ..
+                    } => {
+                        println!("{:#03x}: skipped", cpu.pc);
+                        cpu.skip_instruction();
+                    }
                     _ => {}
                 }
             }
 
             //if we hit n key, want to run one cpu cycle
             if run {
-                let op = cpu.fetch();
                 print!("{:#03x}: ", cpu.pc);
-                cpu.decode_and_execute(op);
-
-                //ST and DT should update cpu_freq/60 times per cpu cycle
-                if cpu.sound.st > 0 {
-                    cpu.sound.st -= (cpu.freq / 60.0) as u8;
+                //the scheduler moves on to a new frame, ticking the timers, when this one runs out
+                //show what the instruction did, whether or not --trace is on
+                let trace = cpu.trace;
+                cpu.trace = true;
+                if let Err(e) = cpu.step_frame() {
+                    println!("{}", e);
                 }
-                if cpu.sound.dt > 0 {
-                    cpu.sound.dt -= (cpu.freq / 60.0) as u8;
+                cpu.trace = trace;
+                if cpu.disp.dirty {
+                    video.draw(&cpu.disp);
+                    cpu.disp.dirty = false;
                 }
+
                 run = false;
             }
 
@@ -1,162 +1,315 @@
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::Sdl;
-use std::ops::BitXorAssign;
-
-use crate::memory::Memory;
+//framebuffer size in superchip hires, lores mode only uses the top left 64x32
+pub const MAX_WIDTH: usize = 128;
+pub const MAX_HEIGHT: usize = 64;
 
 //-----------------------------------------------Display-------------------------------------------
+//plain framebuffer. nothing in here knows how to draw, frontends read pixels and do that.
+//every row of every xo-chip bitplane is one u128 with the leftmost pixel in the top bit, so
+//drawing a sprite line, checking it for collisions and scrolling are a few shifts and masks.
+//a pixel's bits from plane 1 and plane 2 together make its 4 colour palette index
 pub struct Display {
-    pub pixels: [[ScreenPixel; 64]; 32],
-    //NOTE: nessecary for display to actually draw things
-    pub canvas: sdl2::render::Canvas<sdl2::video::Window>,
+    //rows[0] is plane 1, rows[1] is plane 2
+    pub rows: [[u128; MAX_HEIGHT]; 2],
+    //superchip 128x64 mode
+    pub hires: bool,
+    //xo-chip FN01 plane mask, drawing/clearing/scrolling only touch these planes
+    pub planes: u8,
+    //set whenever the framebuffer changes, frontends clear it once they have redrawn
+    pub dirty: bool,
 }
 
 impl Display {
-    pub fn new(sdl_context: &Sdl) -> Display {
-        let mut pixels: [[ScreenPixel; 64]; 32] =
-            [[ScreenPixel::new(Rect::new(0, 0, 10, 10), false); 64]; 32];
-
-        //TODO: can we do this in the initializer?
-        //for i in 0..32 {
-        for (i, row) in pixels.iter_mut().enumerate() {
-            //for j in 0..64 {
-            for (j, column) in row.iter_mut().enumerate() {
-                column.pixel.x = (j * 10) as i32;
-                column.pixel.y = (i * 10) as i32;
-            }
+    pub fn new() -> Display {
+        Display {
+            rows: [[0; MAX_HEIGHT]; 2],
+            hires: false,
+            planes: 1,
+            dirty: true,
         }
+    }
 
-        let video_subsystem = sdl_context.video().unwrap();
+    pub fn width(&self) -> usize {
+        if self.hires {
+            128
+        } else {
+            64
+        }
+    }
 
-        let window = video_subsystem
-            .window("rust-8", 640, 320)
-            .position_centered()
-            .build()
-            .unwrap();
+    pub fn height(&self) -> usize {
+        if self.hires {
+            64
+        } else {
+            32
+        }
+    }
 
-        let canvas = window.into_canvas().build().unwrap();
+    //palette index of the pixel at x, y: bit 0 from plane 1, bit 1 from plane 2
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let bit = MAX_WIDTH - 1 - x;
+        ((self.rows[0][y] >> bit) & 1) as u8 | (((self.rows[1][y] >> bit) & 1) as u8) << 1
+    }
 
-        Display {
-            pixels: pixels,
-            canvas: canvas,
-        }
+    //the bits of a row that are on screen in the current mode
+    fn row_mask(&self) -> u128 {
+        !0 << (MAX_WIDTH - self.width())
     }
 
-    pub fn update_disp(&mut self) {
-        for row in self.pixels {
-            for pixel in row {
-                self.canvas.set_draw_color(Color::BLACK);
-                if pixel.state {
-                    self.canvas.set_draw_color(Color::RED);
-                }
-                self.canvas.fill_rect(pixel.pixel).unwrap();
-            }
-        }
-        self.canvas.present();
+    //the selected planes, as indexes into rows
+    fn selected(&self) -> impl Iterator<Item = usize> {
+        let planes = self.planes;
+        (0..2).filter(move |plane| planes & (1 << plane) != 0)
+    }
+
+    //00FE/00FF, switching modes clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.rows = [[0; MAX_HEIGHT]; 2];
+        self.dirty = true;
     }
 
+    //00E0, only clears the selected planes
     pub fn clear_disp(&mut self) {
-        for (_i, row) in self.pixels.iter_mut().enumerate() {
-            for (_j, pixel) in row.iter_mut().enumerate() {
-                pixel.state = false;
+        for plane in self.selected() {
+            self.rows[plane] = [0; MAX_HEIGHT];
+        }
+        self.dirty = true;
+    }
+
+    //00CN, scroll down n rows, rows coming in at the top are blank
+    pub fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected() {
+            let rows = &mut self.rows[plane];
+            for y in (0..height).rev() {
+                rows[y] = if y >= n { rows[y - n] } else { 0 };
             }
         }
-        //self.update_disp();
-        self.canvas.set_draw_color(Color::BLACK);
-        //self.canvas.fill_rect(None).unwrap();
-        self.canvas.clear();
-        //self.update_disp();
-        self.canvas.present();
-    }
-
-    //XORs a sprite into display buffer
-    //this only updates the state bool, does not actually draw
-    pub fn push_sprite(&mut self, sprite: Sprite, mem: &mut Memory) {
-        let mut draw_over_flag = false;
-        for pixel in sprite.pixels {
-            let x = (pixel.pixel.x / 10) as usize;
-            let y = (pixel.pixel.y / 10) as usize;
-
-            let prev_value = self.pixels[y][x];
-            //println!("XORing pixel at x:{}, y:{}", x, y);
-            self.pixels[y][x].state.bitxor_assign(pixel.state);
-            //println!("pixel at x:{}, y:{} is now: {}", xIndex, yIndex, self.pixels[xIndex][yIndex].state);
-            let new_value = self.pixels[y][x];
-
-            //compliance with drw opcode
-            if prev_value.state == true && new_value.state == false {
-                draw_over_flag = true;
-                /*println!(
-                    "hit xor x:{:x}, y:{:x}, state:{}",
-                    x, y, self.pixels[y][x].state
-                );*/
+        self.dirty = true;
+    }
+
+    //00DN (xo-chip), scroll up n rows
+    pub fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        for plane in self.selected() {
+            let rows = &mut self.rows[plane];
+            for y in 0..height {
+                rows[y] = if y + n < height { rows[y + n] } else { 0 };
             }
         }
+        self.dirty = true;
+    }
 
-        if draw_over_flag {
-            mem.v_regs[0xf] = 0x1;
-        } else {
-            mem.v_regs[0xf] = 0x0;
+    //00FB, scroll right 4 pixels
+    pub fn scroll_right(&mut self) {
+        let mask = self.row_mask();
+        for plane in self.selected() {
+            for row in self.rows[plane].iter_mut() {
+                *row = (*row >> 4) & mask;
+            }
         }
+        self.dirty = true;
     }
-}
-//----------------------
-//represents a pixels as a Rect so SDL can draw it and a boolean to represent if its on or not
-#[derive(Copy, Clone)]
-pub struct ScreenPixel {
-    pub pixel: Rect,
-    pub state: bool,
-}
-impl ScreenPixel {
-    pub fn new(pixel: Rect, state: bool) -> ScreenPixel {
-        ScreenPixel {
-            pixel: pixel,
-            state: state,
+
+    //00FC, scroll left 4 pixels
+    pub fn scroll_left(&mut self) {
+        for plane in self.selected() {
+            for row in self.rows[plane].iter_mut() {
+                *row <<= 4;
+            }
         }
+        self.dirty = true;
     }
-}
-//----------------------
 
-pub struct Sprite {
-    pub pixels: Vec<ScreenPixel>,
-    pub x: i32,
-    pub y: i32,
-}
-impl Sprite {
-    //expects a u8 vector to construct the sprite from
-    pub fn new(sprite: Vec<u8>, x: i32, y: i32) -> Sprite {
-        //pixels of the sprite, represented as sdl rects
-        let mut pixels: Vec<ScreenPixel> = Vec::new();
-
-        //each u8 is a sprite line
-        //for i in 0..sprite.len() {
-        for (i, line) in sprite.iter().enumerate() {
-            //println!("{:#0b}",sprite[i]);
-            //iterate over the bits of the sprite line
-            for j in 0..8 {
-                //for (j, bit) in line.iter().enumerate() {
-                //get bit i of line j using bitwise ops
-                let temp: u8 = (line >> j) & 0x1;
-
-                //if this bit is set, put a square at [x+i][y+j]
-                let rect_x = (x * 10 + ((7 - j) * 10) as i32) % 640;
-                let rect_y = (y * 10 + (i * 10) as i32) % 320;
-
-                if temp == 1 {
-                    //println!("found bit! adding rect at x = {}, y = {}", rectX, rectY);
-                    pixels.push(ScreenPixel::new(Rect::new(rect_x, rect_y, 10, 10), true));
-                } else {
-                    pixels.push(ScreenPixel::new(Rect::new(rect_x, rect_y, 10, 10), false));
-                }
+    //XORs a sprite into one plane (1 or 2), straight from the bytes in memory: one byte per line,
+    //or two if wide (16x16). the starting position always wraps, clip decides whether the rest of
+    //the sprite does too.
+    //returns what VF should become: 0/1 in lores, in hires the number of rows that hit
+    //something or fell off the bottom
+    pub fn draw_sprite(
+        &mut self,
+        plane: u8,
+        data: &[u8],
+        wide: bool,
+        x: u8,
+        y: u8,
+        clip: bool,
+    ) -> u8 {
+        let width = self.width();
+        let height = self.height();
+        let x = x as usize % width;
+        let y = y as usize % height;
+        let cols = if wide { 16 } else { 8 };
+        let rows = &mut self.rows[(plane >> 1) as usize & 1];
+
+        //one bit per screen row, a sprite taller than the screen can hit the same row twice
+        let mut hit_rows: u64 = 0;
+        let mut clipped_rows = 0;
+        for (i, line) in data.chunks(cols / 8).enumerate() {
+            if clip && y + i >= height {
+                clipped_rows += 1;
+                continue;
+            }
+            let line = if wide {
+                (line[0] as u128) << 8 | *line.get(1).unwrap_or(&0) as u128
+            } else {
+                line[0] as u128
+            };
+
+            //work in a width bit lane with column 0 in the top bit, then move it to the top of
+            //the u128. whatever goes past the right edge is cut off or comes back on the left
+            let lane = line << (width - cols);
+            let mut bits = lane >> x;
+            if !clip && x > 0 {
+                bits |= lane << (width - x);
+            }
+            let lane_mask = if width == MAX_WIDTH {
+                !0
+            } else {
+                (1 << width) - 1
+            };
+            let bits = (bits & lane_mask) << (MAX_WIDTH - width);
+
+            let y = (y + i) % height;
+            if rows[y] & bits != 0 {
+                hit_rows |= 1 << y;
             }
+            rows[y] ^= bits;
         }
+        self.dirty = true;
 
-        Sprite {
-            pixels: pixels,
-            x: x * 10,
-            y: y * 10,
+        let hits = hit_rows.count_ones() as u8;
+        if self.hires {
+            hits + clipped_rows
+        } else if hits > 0 {
+            0x1
+        } else {
+            0x0
         }
     }
 }
+
+impl Default for Display {
+    fn default() -> Display {
+        Display::new()
+    }
+}
 //-------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //columns set on a row of plane 1, left to right
+    fn lit(disp: &Display, y: usize) -> Vec<usize> {
+        (0..disp.width())
+            .filter(|x| disp.pixel(*x, y) & 1 != 0)
+            .collect()
+    }
+
+    #[test]
+    fn wraps_off_the_right_edge() {
+        let mut disp = Display::new();
+        disp.draw_sprite(1, &[0xFF], false, 60, 0, false);
+        assert_eq!(lit(&disp, 0), vec![0, 1, 2, 3, 60, 61, 62, 63]);
+    }
+
+    #[test]
+    fn clips_at_the_right_edge() {
+        let mut disp = Display::new();
+        disp.draw_sprite(1, &[0xFF], false, 60, 0, true);
+        assert_eq!(lit(&disp, 0), vec![60, 61, 62, 63]);
+    }
+
+    #[test]
+    fn wraps_and_clips_at_the_bottom() {
+        let mut disp = Display::new();
+        disp.draw_sprite(1, &[0x80, 0x80], false, 0, 31, false);
+        assert_eq!(lit(&disp, 31), vec![0]);
+        assert_eq!(lit(&disp, 0), vec![0]);
+
+        let mut disp = Display::new();
+        assert_eq!(disp.draw_sprite(1, &[0x80, 0x80], false, 0, 31, true), 0);
+        assert_eq!(lit(&disp, 31), vec![0]);
+        assert!(lit(&disp, 0).is_empty());
+    }
+
+    #[test]
+    fn start_position_always_wraps() {
+        let mut disp = Display::new();
+        disp.draw_sprite(1, &[0x80], false, 64 + 5, 32 + 2, true);
+        assert_eq!(lit(&disp, 2), vec![5]);
+    }
+
+    #[test]
+    fn wide_sprites_in_hires() {
+        let mut disp = Display::new();
+        disp.set_hires(true);
+        disp.draw_sprite(1, &[0xFF, 0x01], true, 120, 0, false);
+        assert_eq!(
+            lit(&disp, 0),
+            vec![7, 120, 121, 122, 123, 124, 125, 126, 127]
+        );
+
+        disp.clear_disp();
+        disp.draw_sprite(1, &[0xFF, 0x01], true, 120, 0, true);
+        assert_eq!(lit(&disp, 0), (120..128).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lores_collision_is_a_flag() {
+        let mut disp = Display::new();
+        assert_eq!(disp.draw_sprite(1, &[0xF0, 0xF0], false, 0, 0, false), 0);
+        assert_eq!(disp.draw_sprite(1, &[0x10, 0x10], false, 0, 0, false), 1);
+        //xor turned the overlapping pixels off
+        assert_eq!(lit(&disp, 0), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn hires_counts_hit_and_clipped_rows() {
+        let mut disp = Display::new();
+        disp.set_hires(true);
+        //two rows on screen, two off the bottom
+        assert_eq!(disp.draw_sprite(1, &[0xFF; 4], false, 0, 62, true), 2);
+        assert_eq!(disp.draw_sprite(1, &[0xFF; 4], false, 0, 62, true), 4);
+        //a sprite wrapping onto the same row twice only counts it once
+        let mut disp = Display::new();
+        disp.set_hires(true);
+        disp.draw_sprite(1, &[0xC0], false, 0, 0, false);
+        let mut tall = [0; 65];
+        tall[0] = 0x80;
+        tall[64] = 0xC0;
+        assert_eq!(disp.draw_sprite(1, &tall, false, 0, 0, false), 1);
+    }
+
+    #[test]
+    fn planes_are_separate() {
+        let mut disp = Display::new();
+        disp.draw_sprite(2, &[0x80], false, 0, 0, false);
+        assert_eq!(disp.pixel(0, 0), 2);
+        assert_eq!(disp.draw_sprite(1, &[0x80], false, 0, 0, false), 0);
+        assert_eq!(disp.pixel(0, 0), 3);
+
+        //only selected planes are cleared
+        disp.planes = 2;
+        disp.clear_disp();
+        assert_eq!(disp.pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn scrolling_stays_on_screen() {
+        let mut disp = Display::new();
+        disp.draw_sprite(1, &[0x01], false, 56, 0, false);
+        disp.scroll_right();
+        //pixel 63 went off the edge instead of into the hidden hires columns
+        assert_eq!(disp.rows[0][0], 0);
+        disp.set_hires(true);
+
+        disp.draw_sprite(1, &[0x80], false, 4, 0, false);
+        disp.scroll_left();
+        disp.scroll_down(3);
+        assert_eq!(lit(&disp, 3), vec![0]);
+        disp.scroll_up(1);
+        assert_eq!(lit(&disp, 2), vec![0]);
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+//-----------------------------------------------Errors--------------------------------------------
+//everything that can stop the emulator. none of these panic, the frontend decides what to do
+#[derive(Debug)]
+pub enum EmuError {
+    //opcode that no supported interpreter defines, and where it was
+    BadOpcode {
+        addr: u16,
+        op: u16,
+    },
+    //CALL with every stack slot in use, chain holds the CALLs already on the stack, outermost first
+    StackOverflow {
+        addr: u16,
+        chain: Vec<u16>,
+    },
+    //RET with nothing on the stack
+    StackUnderflow {
+        addr: u16,
+    },
+    //an instruction at addr tried to touch len bytes of memory starting at target
+    OutOfBounds {
+        addr: u16,
+        target: usize,
+        len: usize,
+    },
+    //rom does not fit between the program base and the end of memory
+    RomTooLarge {
+        size: usize,
+        max: usize,
+    },
+    //reading the rom file failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::BadOpcode { addr, op } => write!(f, "bad opcode {:04x} at {:#05x}", op, addr),
+            EmuError::StackOverflow { addr, chain } => {
+                write!(
+                    f,
+                    "stack overflow at {:#05x}, {} calls deep:",
+                    addr,
+                    chain.len()
+                )?;
+                for call in chain.iter() {
+                    write!(f, " {:#05x}", call)?;
+                }
+                Ok(())
+            }
+            EmuError::StackUnderflow { addr } => write!(f, "stack underflow at {:#05x}", addr),
+            EmuError::OutOfBounds { addr, target, len } => write!(
+                f,
+                "out of bounds access of {} bytes at {:#x} by instruction at {:#05x}",
+                len, target, addr
+            ),
+            EmuError::RomTooLarge { size, max } => {
+                write!(f, "rom is {} bytes, at most {} fit in memory", size, max)
+            }
+            EmuError::Io(e) => write!(f, "could not read rom: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmuError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for EmuError {
+    fn from(e: std::io::Error) -> EmuError {
+        EmuError::Io(e)
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,27 @@
+use crate::display::Display;
+use crate::input::Input;
+use crate::sound::Sound;
+
+//-----------------------------------------------Frontend------------------------------------------
+//the core never talks to a window, speaker or keyboard itself.
+//a frontend implements these and its main loop moves data between them and the CPU
+
+//puts the framebuffer somewhere a human can see it
+pub trait Video {
+    //redraw the whole framebuffer
+    fn draw(&mut self, disp: &Display);
+}
+
+//makes noise while the sound timer is running
+pub trait Audio {
+    //called regularly, collects what the core rendered with Sound::take_samples
+    fn update(&mut self, sound: &mut Sound);
+}
+
+//turns frontend specific events (sdl events, a script, a network packet...) into keypad state
+pub trait InputSource {
+    type Event;
+
+    fn update(&mut self, input: &mut Input, event: &Self::Event);
+}
+//-------------------------------------------------------------------------------------------------
@@ -1,73 +1,95 @@
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::time::Instant;
 //-----------------------------------------------Input----------------------------------------------
+//state of the 16 key hex keypad. frontends call press/release, the cpu only ever reads it
 pub struct Input {
-    pub keys: [bool;16],
-    //nessecary for halt until keypress
-    pub last_key: u8,
-    pub lu: Instant,
+    pub keys: [bool; 16],
+    //keys that went down / came up since FX0A last looked, one bit per key. latched so a tap that
+    //starts and ends between two instructions is still seen
+    pub pressed: u16,
+    pub released: u16,
+    //how many keyboard keys and pad buttons hold each key down, it only comes up once the last
+    //of them lets go. this is the host's side of things, so save states leave it alone
+    holders: [u8; 16],
 }
 
 impl Input {
     pub fn new() -> Input {
-        Input { keys: [false;16], last_key: 0 ,lu: Instant::now()}
+        Input {
+            keys: [false; 16],
+            pressed: 0,
+            released: 0,
+            holders: [0; 16],
+        }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        let holders = &mut self.holders[key as usize];
+        *holders = holders.saturating_add(1);
+        if !self.keys[key as usize] {
+            self.keys[key as usize] = true;
+            self.pressed |= 1 << key;
+        }
+    }
+
+    pub fn release(&mut self, key: u8) {
+        let holders = &mut self.holders[key as usize];
+        *holders = holders.saturating_sub(1);
+        if *holders == 0 && self.keys[key as usize] {
+            self.keys[key as usize] = false;
+            self.released |= 1 << key;
+        }
+    }
+
+    //presses and releases since the last call, as bitmasks
+    pub fn take_edges(&mut self) -> (u16, u16) {
+        let edges = (self.pressed, self.released);
+        self.pressed = 0;
+        self.released = 0;
+        edges
     }
 
-    pub fn update(&mut self, event: sdl2::event::Event) {
-            match event{
-                Event::KeyDown{keycode: Some(keycodevar), ..} =>
-                    {self.lu = Instant::now();
-                    match keycodevar{
-                        Keycode::Num1 =>{self.keys[1] = true; self.last_key = 1},
-                        Keycode::Num2 =>{self.keys[2] =true; self.last_key = 2},
-                        Keycode::Num3 =>{self.keys[3] = true; self.last_key = 3},
-                        Keycode::Num4 =>{self.keys[0xc] = true; self.last_key = 0xc},
-                        Keycode::Q => {self.keys[4] = true; self.last_key = 4},
-                        Keycode::W => {self.keys[5] = true; self.last_key = 5},
-                        Keycode::E => {self.keys[6] = true; self.last_key = 6},
-                        Keycode::R => {self.keys[0xd] = true; self.last_key = 0xd},
-                        Keycode::A => {self.keys[7] = true; self.last_key = 7},
-                        Keycode::S => {self.keys[8] = true; self.last_key = 8},
-                        Keycode::D => {self.keys[9] = true; self.last_key = 9},
-                        Keycode::F => {self.keys[0xe] = true; self.last_key = 0xe},
-                        Keycode::Z => {self.keys[0xa] = true; self.last_key = 0xa},
-                        Keycode::X => {self.keys[0] = true; self.last_key = 0},
-                        Keycode::C => {self.keys[0xb] = true; self.last_key = 0xb},
-                        Keycode::V => {self.keys[0xf] = true; self.last_key = 0xf},
-                        _ =>{}
-                    }},
-                Event::KeyUp{keycode: Some(keycodevar), ..} =>
-                    match keycodevar{
-                        Keycode::Num1 =>self.keys[1] = false,
-                        Keycode::Num2 =>self.keys[2] = false,
-                        Keycode::Num3 =>self.keys[3] = false,
-                        Keycode::Num4 =>self.keys[0xc] = false,
-                        Keycode::Q => self.keys[4] = false,
-                        Keycode::W => self.keys[5] = false,
-                        Keycode::E => self.keys[6] = false,
-                        Keycode::R => self.keys[0xd] = false,
-                        Keycode::A => self.keys[7] = false,
-                        Keycode::S => self.keys[8] = false,
-                        Keycode::D => self.keys[9] = false,
-                        Keycode::F => self.keys[0xe] = false,
-                        Keycode::Z => self.keys[0xa] = false,
-                        Keycode::X => self.keys[0] = false,
-                        Keycode::C => self.keys[0xb] = false,
-                        Keycode::V => self.keys[0xf] = false,
-                        _ =>{}
-                    },
-                _ => {}
+    //all 16 keys as a bitmask, bit n is key n
+    pub fn mask(&self) -> u16 {
+        self.keys
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (key, down)| mask | (*down as u16) << key)
+    }
+
+    //bring the keys to a recorded bitmask through press/release, like a keyboard would
+    pub fn set_mask(&mut self, mask: u16) {
+        for key in 0..16u8 {
+            let down = mask & (1 << key) != 0;
+            if down && !self.keys[key as usize] {
+                self.press(key);
+            } else if !down && self.keys[key as usize] {
+                self.release(key);
+            }
         }
+    }
 
+    pub fn dump(&mut self) {
+        println!(
+            "{} {} {} {}",
+            self.keys[1], self.keys[2], self.keys[3], self.keys[0xc]
+        );
+        println!(
+            "{} {} {} {}",
+            self.keys[4], self.keys[5], self.keys[6], self.keys[0xd]
+        );
+        println!(
+            "{} {} {} {}",
+            self.keys[7], self.keys[8], self.keys[9], self.keys[0xe]
+        );
+        println!(
+            "{} {} {} {}",
+            self.keys[0xa], self.keys[0], self.keys[0xb], self.keys[0xf]
+        );
     }
+}
 
-    pub fn dump(&mut self){
-        println!("{} {} {} {}",self.keys[1],self.keys[2],self.keys[3],self.keys[0xc]);
-        println!("{} {} {} {}",self.keys[4],self.keys[5],self.keys[6],self.keys[0xd]);
-        println!("{} {} {} {}",self.keys[7],self.keys[8],self.keys[9],self.keys[0xe]);
-        println!("{} {} {} {}",self.keys[0xa],self.keys[0],self.keys[0xb],self.keys[0xf]);
+impl Default for Input {
+    fn default() -> Input {
+        Input::new()
     }
 }
 //-------------------------------------------------------------------------------------------------
@@ -0,0 +1,33 @@
+//! rust-8 emulator core.
+//!
+//! Everything in here is frontend agnostic: no windows, no audio devices, no event loops.
+//! A frontend (the sdl binary in main.rs, a test harness, some other tool) owns a [`cpu::CPU`],
+//! steps it, and implements the traits in [`frontend`] to show its framebuffer, play its sound
+//! and feed it keys.
+
+//modules for components
+pub mod cpu;
+pub mod display;
+pub mod error;
+pub mod frontend;
+pub mod input;
+pub mod memory;
+pub mod movie;
+pub mod pacer;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod rom;
+pub mod savestate;
+pub mod scheduler;
+pub mod sound;
+pub mod sys;
+
+pub use crate::cpu::CPU;
+pub use crate::display::Display;
+pub use crate::error::EmuError;
+pub use crate::input::Input;
+pub use crate::memory::Memory;
+pub use crate::quirks::Quirks;
+pub use crate::rom::ROM;
+pub use crate::sound::Sound;
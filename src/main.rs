@@ -1,35 +1,46 @@
 extern crate sdl2;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Scancode;
 
 use std::env;
-use std::time::Duration;
+use std::fs;
 
-use std::time::Instant;
+//frontend modules
+mod args;
+use crate::args::{Args, BadOpPolicy};
+
+mod config;
+use crate::config::Config;
 
-//modules for components
 mod debugger;
 use crate::debugger::Debugger as Debug;
 
-mod rom;
-use crate::rom::ROM;
-
-mod memory;
-use crate::memory::Memory;
-
-mod display;
-use crate::display::Display;
-use crate::display::Sprite;
-
-mod sound;
-use crate::sound::Sound;
-
-mod input;
-use crate::input::Input;
-
-mod cpu;
-use crate::cpu::CPU;
+mod sdl;
+
+mod slots;
+use crate::sdl::afterglow::Afterglow;
+use crate::sdl::audio::SdlAudio;
+use crate::sdl::controller::SdlController;
+use crate::sdl::input::SdlInput;
+use crate::sdl::upscale::Upscaler;
+use crate::sdl::video::SdlVideo;
+
+//emulator core
+use rust_8::display::Display;
+use rust_8::frontend::{Audio, InputSource, Video};
+use rust_8::input::Input;
+use rust_8::memory::Memory;
+use rust_8::movie::{Movie, MoviePlayer};
+use rust_8::pacer::Pacer;
+use rust_8::rewind::Rewind;
+use rust_8::rng::Rng;
+use rust_8::rom::ROM;
+use rust_8::scheduler::{Scheduler, FRAME_RATE};
+use rust_8::sound::Sound;
+use rust_8::sys::SysCalls;
+use rust_8::EmuError;
+use rust_8::CPU;
 
 fn main() {
     //-------------------------------------------SDL setup-----------------------------------------
@@ -38,20 +49,44 @@ fn main() {
     //---------------------------------------------------------------------------------------------
 
     //------------------------------------User Input-----------------------------------------------
-    let args: Vec<String> = env::args().collect();
-    //make sure we got a rom filename
-    if args.len() < 2 {
-        println!("must pass a rom filename!\nExiting.");
-        return;
+    let cli: Vec<String> = env::args().collect();
+    //make sure we got a rom filename and sane options
+    let args = match Args::parse(&cli) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}\n{}\nExiting.", e, args::USAGE);
+            return;
+        }
     };
-
-    let mut start_debugging = false;
-    if args.len() == 3 {
-        start_debugging = true;
+    //the config file knows the rom now, parse again with its options in front of the real ones
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{}\nExiting.", e);
+            return;
+        }
+    };
+    let mut full = vec![cli[0].clone()];
+    match config.args_for(&args.rom, args::FLAGS, args::OPTIONS) {
+        Ok(config_args) => full.extend(config_args),
+        Err(e) => {
+            println!("{}\nExiting.", e);
+            return;
+        }
     }
+    full.extend(cli.iter().skip(1).cloned());
+    let args = match Args::parse(&full) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{} (in config)\n{}\nExiting.", e, args::USAGE);
+            return;
+        }
+    };
+
+    let start_debugging = args.debug;
 
     //let filename = "/home/chris/Documents/projects/rust-8/roms/".to_owned() + &args[1];
-    let filename = "./".to_owned() + &args[1];
+    let filename = "./".to_owned() + &args.rom;
     //DEBUG: print full filepath to ROM
     //println!("{}",filename);
     //---------------------------------------------------------------------------------------------
@@ -64,46 +99,127 @@ fn main() {
     }
 
     //Memory - includes regs and rom
-    let rom = ROM::new(&filename);
+    let rom = match ROM::new(&filename) {
+        Ok(rom) => rom,
+        Err(e) => {
+            println!("{}\nExiting.", e);
+            return;
+        }
+    };
     let mut mem = Memory::new();
-    mem.init(&rom);
+    mem.layout = args.layout;
+    if let Err(e) = mem.init(&rom) {
+        println!("{}\nExiting.", e);
+        return;
+    }
     //mem.dump();
 
     //Disp
-    let disp = Display::new(&sdl_context);
+    let disp = Display::new();
+    let canvas = SdlVideo::canvas(&sdl_context, args.vsync, args.scale, args.fullscreen);
+    //the video frontend's texture borrows this, so it lives out here
+    let texture_creator = canvas.texture_creator();
+    let mut video = SdlVideo::new(
+        canvas,
+        &texture_creator,
+        args.scaling,
+        Upscaler::new(args.smoothing, args.overlay),
+    );
+    video.set_palette(args.palette.clone());
+    video.afterglow = Afterglow::new(args.phosphor, args.blend);
 
     //DEBUG push a sprite and manually refresh display
 
     //Sound
     let sound = Sound::new();
+    let mut audio = SdlAudio::new(&sdl_context);
 
     //Input
     let input = Input::new();
+    let mut keypad = match SdlInput::configure(args.keymap.as_deref(), &args.keys) {
+        Ok(keypad) => keypad,
+        Err(e) => {
+            println!("{}\nExiting.", e);
+            return;
+        }
+    };
+    let pad_bindings = match SdlController::configure(args.pad.as_deref(), &args.rom, &args.buttons)
+    {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            println!("{}\nExiting.", e);
+            return;
+        }
+    };
+    let mut pads = SdlController::new(&sdl_context, pad_bindings, args.rumble);
 
     //---------------------------------------------------------------------------------------------
 
     //------------------------------------CPU main loop--------------------------------------------
     let mut cpu = CPU::new(mem, disp, sound, input);
+    cpu.quirks = args.quirks;
+    cpu.trace = args.trace;
+    cpu.scheduler = Scheduler::new(args.speed);
+    cpu.mem.stack_depth = args.stack_depth;
+    cpu.rom_hash = rom.hash();
+    cpu.rng = Rng::new(args.seed.unwrap_or_else(Rng::random_seed));
+    cpu.rng.mode = args.rng_mode;
+    if args.sys {
+        cpu.sys = SysCalls::vip();
+    }
+    cpu.sound.beeper = args.beeper;
+    //says when to run frames so they come out at 60 per second
+    let mut pacer = Pacer::new(FRAME_RATE);
+
+    //one snapshot per frame, backspace plays them back
+    let mut rewind = Rewind::new(args.rewind_seconds * 60);
+    let mut rewinding = false;
+
+    //movies take over the keypad: live keys land in pending and only reach the cpu on frame
+    //boundaries, so what gets recorded is exactly what the cpu saw
+    let mut pending = Input::new();
+    let mut recording = args.record.as_ref().map(|_| Movie::new(&cpu));
+    let mut playing = match &args.play {
+        Some(path) => {
+            let movie = match fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| Movie::from_bytes(&data).map_err(|e| e.to_string()))
+            {
+                Ok(movie) => movie,
+                Err(e) => {
+                    println!("could not load movie {}: {}\nExiting.", path, e);
+                    return;
+                }
+            };
+            let (player, check) = MoviePlayer::new(movie, &mut cpu);
+            if let Err(e) = check {
+                println!("movie desync: {}", e);
+            }
+            println!(
+                "playing movie {} ({} frames)",
+                path,
+                player.movie.frames.len()
+            );
+            Some(player)
+        }
+        None => None,
+    };
+    //after the movie, which brings its own seed
+    println!("rng seed: {}", cpu.rng.seed);
 
-    let mut test_sprite: Vec<u8> = Vec::new();
-    test_sprite.push(0b11111111);
-    test_sprite.push(0b10000001);
-    test_sprite.push(0b10000001);
-    test_sprite.push(0b11111111);
-    let test_sprite_2: Vec<u8> = test_sprite.clone();
+    let test_sprite: [u8; 4] = [0b11111111, 0b10000001, 0b10000001, 0b11111111];
+    let test_sprite_2: [u8; 4] = test_sprite;
     println!("test sprite 1: {:?}", test_sprite);
     println!("test sprite 2: {:?}", test_sprite_2);
 
     println!("drawing test sprite");
-    cpu.disp
-        .push_sprite(Sprite::new(test_sprite, 0, 0), &mut cpu.mem);
-    cpu.disp.update_disp();
+    cpu.disp.draw_sprite(1, &test_sprite, false, 0, 0, false);
+    video.draw(&cpu.disp);
     //::std::thread::sleep(Duration::from_secs(5));
 
     /*println!("drawing sprite again to test xor");
-    cpu.disp
-        .push_sprite(Sprite::new(test_sprite_2, 0, 0), &mut cpu.mem);
-    cpu.disp.update_disp();
+    cpu.disp.draw_sprite(1, &test_sprite_2, false, 0, 0, false);
+    video.draw(&cpu.disp);
     ::std::thread::sleep(Duration::from_secs(5));*/
 
     println!("clearing display!");
@@ -112,73 +228,195 @@ fn main() {
 
     for i in 0..32 {
         for j in 0..64 {
-            assert_eq!(cpu.disp.pixels[i][j].state, false);
+            assert_eq!(cpu.disp.pixel(j, i), 0);
         }
     }
 
     println!("calling update to make sure we actually cleared");
-    cpu.disp.update_disp();
+    video.draw(&cpu.disp);
+    cpu.disp.dirty = false;
     //::std::thread::sleep(Duration::from_secs(5));
 
     'running: loop {
         for event in event_pump.poll_iter() {
+            //rewinding and loading states change the machine behind the movie's back, it could
+            //never be played back the same, so they are off while one runs
+            let in_movie = recording.is_some() || playing.is_some();
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+                Event::Quit { .. } => break 'running,
+                //on key press or key release, update our input bool struct
+                Event::KeyDown { .. } | Event::KeyUp { .. } if keypad.binds(&event) => {
+                    if in_movie {
+                        keypad.update(&mut pending, &event)
+                    } else {
+                        keypad.update(&mut cpu.input, &event)
+                    }
+                }
+                //hotkeys go by position like the keypad, so they stay put on other layouts
+                Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
                     ..
                 } => break 'running,
                 Event::KeyDown {
-                    keycode: Some(Keycode::Space),
+                    scancode: Some(Scancode::Space),
                     ..
                 } => {
                     debugger.live = true;
                     break;
                 }
-                //on key press or key release, update our input bool struct
-                Event::KeyDown { .. } | Event::KeyUp { .. } => cpu.input.update(event),
+                Event::KeyDown {
+                    scancode: Some(Scancode::Backspace),
+                    repeat: false,
+                    ..
+                } if in_movie => println!("rewind is off while a movie is recording or playing"),
+                Event::KeyDown {
+                    scancode: Some(Scancode::Backspace),
+                    ..
+                } if !in_movie => rewinding = true,
+                Event::KeyUp {
+                    scancode: Some(Scancode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::KeyDown {
+                    scancode: Some(Scancode::M),
+                    ..
+                } => cpu.sound.beeper.muted = !cpu.sound.beeper.muted,
+                Event::KeyDown {
+                    scancode: Some(Scancode::F11),
+                    ..
+                } => video.toggle_fullscreen(),
+                Event::KeyDown {
+                    scancode: Some(Scancode::P),
+                    ..
+                } => {
+                    println!("palette: {}", video.next_palette().name);
+                    cpu.disp.dirty = true;
+                }
+                //the picture has to be fitted into the new size
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => cpu.disp.dirty = true,
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    keymod,
+                    ..
+                } if slots::slot_for(scancode).is_some() => slots::handle(
+                    &mut cpu,
+                    &filename,
+                    slots::slot_for(scancode).unwrap(),
+                    keymod,
+                    !in_movie,
+                ),
+                Event::ControllerDeviceAdded { .. }
+                | Event::ControllerDeviceRemoved { .. }
+                | Event::ControllerButtonDown { .. }
+                | Event::ControllerButtonUp { .. } => {
+                    if let Some(pads) = pads.as_mut() {
+                        if in_movie {
+                            pads.update(&mut pending, &event)
+                        } else {
+                            pads.update(&mut cpu.input, &event)
+                        }
+                    }
+                }
                 _ => {}
             }
         }
 
         if debugger.live {
             println!("going to debugger");
-            debugger.run(&mut event_pump, &mut cpu);
+            debugger.run(&mut event_pump, &mut cpu, &mut video);
+            pacer.reset();
         }
 
-        //execute cpu op
-        let raw_op = cpu.fetch();
-        print!("{:#03x}: ", cpu.pc);
-        cpu.decode_and_execute(raw_op);
-        if cpu.crashed {
-            //debugger.live = true;
-            //debugger.run(&mut event_pump, &mut cpu);
-            break 'running;
+        //run however many frames are due, normally one, more to catch up after a short stall
+        for _ in 0..pacer.due() {
+            //while rewinding, recorded frames play backwards instead of running the cpu
+            if rewinding {
+                rewind.rewind(&mut cpu);
+                video.end_frame(&cpu.disp);
+                continue;
+            }
+
+            //input for this frame
+            if let Some(player) = playing.as_mut() {
+                if !player.next_frame(&mut cpu.input) {
+                    println!("movie finished, keyboard is live again");
+                    playing = None;
+                }
+            } else if let Some(movie) = recording.as_mut() {
+                cpu.input.set_mask(pending.mask());
+                movie.record(&cpu.input);
+            }
+
+            //run the frame, skipped bad opcodes just let it carry on
+            let result = loop {
+                match cpu.run_frame() {
+                    Err(e @ EmuError::BadOpcode { .. }) if args.bad_op == BadOpPolicy::Skip => {
+                        println!("{}", e);
+                        cpu.skip_instruction();
+                    }
+                    result => break result,
+                }
+            };
+            match result {
+                Ok(()) => {}
+                //the frame is left half done, it picks up again once the debugger is closed.
+                //S in the debugger steps over the bad opcode, otherwise it is hit again
+                Err(e @ EmuError::BadOpcode { .. }) if args.bad_op == BadOpPolicy::Debug => {
+                    println!("{}", e);
+                    debugger.live = true;
+                    debugger.run(&mut event_pump, &mut cpu, &mut video);
+                    pacer.reset();
+                    break;
+                }
+                Err(e) => {
+                    println!("{}", e);
+                    break 'running;
+                }
+            }
+            if cpu.exited {
+                break 'running;
+            }
+            rewind.push(&cpu);
+            video.end_frame(&cpu.disp);
+        }
+
+        //with vsync on, presenting blocks until the display refreshes and that paces the loop.
+        //afterglow keeps changing the picture after the framebuffer stops
+        if cpu.disp.dirty || args.vsync || video.afterglow.active() {
+            video.draw(&cpu.disp);
+            cpu.disp.dirty = false;
+        }
+        audio.update(&mut cpu.sound);
+        if let Some(pads) = pads.as_mut() {
+            pads.update_rumble(cpu.sound.st > 0);
         }
 
-        //this executes once per clock cycle
-        //always increment the #clock cycles since last update
-        cpu.sound.dt_lu += 1;
-        //if we have gone through 500/60 = 8.33  cycles, and the reg is non-zero, decrement
-        if cpu.sound.dt_lu as f32 >= (cpu.freq / 60.0) as f32 && cpu.sound.dt > 0 {
-            //println!("WE ARE DECREMENTING DT");
-            //::std::thread::sleep(Duration::from_secs(5));
-            cpu.sound.dt -= 1;
+        if let Some(report) = pacer.report() {
+            let mut title = format!(
+                "rust-8 - {:.1}/{} fps ({:.0}%)",
+                report.fps,
+                FRAME_RATE,
+                report.speed * 100.0
+            );
+            if report.dropped > 0 {
+                title.push_str(&format!(", {} dropped", report.dropped));
+            }
+            video.set_title(&title);
         }
-        //println!("dt is {} and dt_lu is {}", cpu.sound.dt, cpu.sound.dt_lu);
 
-        cpu.sound.st_lu += 1;
-        if cpu.sound.st_lu as f32 >= (cpu.freq / 60.0) as f32 && cpu.sound.st > 0 {
-            cpu.sound.st -= 1;
+        if !args.vsync {
+            pacer.wait();
         }
+    }
 
-        //fencing for the cpu clock
-        //if it has not been 1/freq * 1.0x10^9 seconds since the last cycle,wait a ns until it is
-        while cpu.lcc.elapsed().as_nanos() < ((1.0 / cpu.freq) * 1_000_000_000.0) as u128 {
-            ::std::thread::sleep(Duration::from_nanos(1));
+    if let (Some(movie), Some(path)) = (recording, &args.record) {
+        match fs::write(path, movie.to_bytes()) {
+            Ok(()) => println!("recorded {} frames to {}", movie.frames.len(), path),
+            Err(e) => println!("could not write movie {}: {}", path, e),
         }
-        //update cpu's LCC
-        cpu.lcc = Instant::now();
     }
     //---------------------------------------------------------------------------------------------
 }
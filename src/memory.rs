@@ -1,34 +1,85 @@
+use crate::error::EmuError;
 use crate::rom::ROM;
 
+//superchip 8x10 digits live right after the 4x5 font
+pub const BIG_FONT_BASE: u16 = 0x50;
+//superchip and everything after it had room for 16 return addresses
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+
+//where the COSMAC VIP interpreter kept its own state in RAM. everything from 0xEA0 up is the
+//interpreter's: the stack grows down from 0xECF, 0xED0-0xEEF is its scratch space and V0-VF live
+//at 0xEF0. 0xF00 onwards was the display
+pub const VIP_RESERVED: u16 = 0xEA0;
+pub const VIP_STACK_TOP: u16 = 0xED0;
+pub const VIP_REGS: u16 = 0xEF0;
+
+//where the interpreter keeps the things a program is not supposed to see
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Layout {
+    //stack and registers live outside of addressable memory
+    Separate,
+    //stack and registers sit in mem at the VIP addresses, so programs can read and clobber them
+    Vip,
+}
+
+impl Layout {
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name.to_lowercase().as_str() {
+            "separate" | "default" => Some(Layout::Separate),
+            "vip" => Some(Layout::Vip),
+            _ => None,
+        }
+    }
+}
+
 //---------------------------------------------Memory----------------------------------------------
 #[allow(non_snake_case)]
 pub struct Memory {
-    //memory
-    pub mem: [u8; 4096],
+    //memory, xo-chip sized. plain chip8 programs just never look past 4KB
+    pub mem: [u8; 65536],
     pub program_base: i32,
 
     //registers
     pub v_regs: [u8; 16],
     pub I: u16,
 
-    //stack
-    pub sp: u8,
-    pub stack: [u16; 16],
+    //return addresses of the CALLs in progress, innermost last
+    pub stack: Vec<u16>,
+    //how many CALLs can nest before the stack overflows, None never overflows (for debugging)
+    pub stack_depth: Option<usize>,
+    pub layout: Layout,
+
+    //superchip RPL user flags, FX75/FX85 save and restore V0..VX here
+    pub rpl: [u8; 16],
 }
 
 impl Memory {
     pub fn new() -> Memory {
         Memory {
-            mem: [0; 4096],
+            mem: [0; 65536],
             program_base: 512,
             v_regs: [0; 16],
             I: 0,
-            sp: 0,
-            stack: [0; 16],
+            stack: Vec::new(),
+            stack_depth: Some(DEFAULT_STACK_DEPTH),
+            layout: Layout::Separate,
+            rpl: [0; 16],
         }
     }
 
-    pub fn init(&mut self, rom: &ROM) {
+    pub fn init(&mut self, rom: &ROM) -> Result<(), EmuError> {
+        let end = match self.layout {
+            Layout::Separate => self.mem.len(),
+            Layout::Vip => VIP_RESERVED as usize,
+        };
+        let max = end.saturating_sub(self.program_base as usize);
+        if rom.buffer.len() > max {
+            return Err(EmuError::RomTooLarge {
+                size: rom.buffer.len(),
+                max,
+            });
+        }
+
         //copy ROM data to memory starting at 0x200(512)
         self.mem
             [self.program_base as usize..(self.program_base + (rom.buffer.len() as i32)) as usize]
@@ -62,6 +113,103 @@ impl Memory {
         ];
 
         self.mem[0..font_sprites.len()].copy_from_slice(&font_sprites);
+
+        //superchip big hex digits, 10 bytes each. schip 1.1 only had 0-9, A-F are from octo
+        let big_font_sprites: Vec<u8> = vec![
+            0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+            0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+            0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+            0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+        let big_font_base = BIG_FONT_BASE as usize;
+        self.mem[big_font_base..big_font_base + big_font_sprites.len()]
+            .copy_from_slice(&big_font_sprites);
+
+        Ok(())
+    }
+
+    //false if the stack is already full, the address is not pushed then
+    pub fn push_return(&mut self, addr: u16) -> bool {
+        if self
+            .stack_depth
+            .is_some_and(|depth| self.stack.len() >= depth)
+        {
+            return false;
+        }
+        if self.layout == Layout::Vip {
+            let slot = self.vip_stack_slot(self.stack.len());
+            self.mem[slot] = (addr >> 8) as u8;
+            self.mem[(slot + 1) & 0xFFFF] = addr as u8;
+        }
+        self.stack.push(addr);
+        true
+    }
+
+    //None if there is nothing to return to
+    pub fn pop_return(&mut self) -> Option<u16> {
+        let addr = self.stack.pop()?;
+        Some(self.return_addr(self.stack.len()).unwrap_or(addr))
+    }
+
+    //address of every CALL still in progress, outermost first
+    pub fn call_chain(&self) -> Vec<u16> {
+        (0..self.stack.len())
+            .filter_map(|level| self.return_addr(level))
+            .map(|addr| addr.wrapping_sub(2))
+            .collect()
+    }
+
+    //return address saved for the given nesting level. with the vip layout it comes from mem, which
+    //is whatever the program left there
+    fn return_addr(&self, level: usize) -> Option<u16> {
+        match self.layout {
+            Layout::Separate => self.stack.get(level).copied(),
+            Layout::Vip => {
+                let slot = self.vip_stack_slot(level);
+                Some((self.mem[slot] as u16) << 8 | self.mem[(slot + 1) & 0xFFFF] as u16)
+            }
+        }
+    }
+
+    //each level takes two bytes going down from the stack top, an unlimited stack just keeps
+    //going into the program like it would on the real thing
+    fn vip_stack_slot(&self, level: usize) -> usize {
+        VIP_STACK_TOP.wrapping_sub(2 * (level as u16 + 1)) as usize
+    }
+
+    //with the vip layout V0-VF are read from mem before every instruction...
+    pub fn load_vip_regs(&mut self) -> [u8; 16] {
+        if self.layout == Layout::Vip {
+            let regs = VIP_REGS as usize;
+            self.v_regs.copy_from_slice(&self.mem[regs..regs + 16]);
+        }
+        self.v_regs
+    }
+
+    //...and written back after it. only registers the instruction changed are copied, so a store
+    //into the register area (FX33, FX55 with I pointed there) sticks
+    pub fn store_vip_regs(&mut self, before: [u8; 16]) {
+        if self.layout != Layout::Vip {
+            return;
+        }
+        let regs = VIP_REGS as usize;
+        for (i, (now, was)) in self.v_regs.iter().zip(before.iter()).enumerate() {
+            if now != was {
+                self.mem[regs + i] = *now;
+            }
+        }
     }
 
     //dump memory to console
@@ -93,7 +241,7 @@ impl Memory {
 
         for i in 0..lines {
             for j in 0..8 {
-                line.push_str(&*format!(
+                line.push_str(&format!(
                     "{:02x}{:02x} ",
                     self.mem[base + (i * 16) + (j * 2)],
                     self.mem[base + (i * 16) + ((j * 2) + 1)],
@@ -102,7 +250,13 @@ impl Memory {
         }
 
         //println!("{}", line);
-        return line;
+        line
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
     }
 }
 //-------------------------------------------------------------------------------------------------
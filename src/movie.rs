@@ -0,0 +1,180 @@
+use crate::cpu::CPU;
+use crate::input::Input;
+use crate::memory::Layout;
+use crate::quirks::Quirks;
+use crate::rng::{Rng, RngMode};
+use crate::savestate::{StateError, StateReader, StateWriter};
+use crate::scheduler::{Scheduler, Speed};
+
+pub const MAGIC: &[u8; 4] = b"R8MV";
+pub const VERSION: u16 = 3;
+
+//-----------------------------------------------Movie---------------------------------------------
+//keypad state for every emulated frame since power on, plus everything else needed to make the
+//same rom do the same thing again: the rng seed, the quirks, the speed, the stack and which 0NNN
+//routines it ran with
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub rng_mode: RngMode,
+    pub quirks: Quirks,
+    pub speed: Speed,
+    pub stack_depth: Option<usize>,
+    pub layout: Layout,
+    //addresses of the 0NNN routines, these are native code so they are checked rather than restored
+    pub sys: Vec<u16>,
+    //Input::mask per frame
+    pub frames: Vec<u16>,
+}
+
+impl Movie {
+    //start a recording for a freshly reset machine
+    pub fn new(cpu: &CPU) -> Movie {
+        Movie {
+            rom_hash: cpu.rom_hash,
+            seed: cpu.rng.seed,
+            rng_mode: cpu.rng.mode,
+            quirks: cpu.quirks,
+            speed: cpu.scheduler.speed,
+            stack_depth: cpu.mem.stack_depth,
+            layout: cpu.mem.layout,
+            sys: cpu.sys.addresses(),
+            frames: Vec::new(),
+        }
+    }
+
+    //call once per frame, after the frame's input has been applied
+    pub fn record(&mut self, input: &Input) {
+        self.frames.push(input.mask());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w.u64(self.rom_hash);
+        w.u64(self.seed);
+        w.u8(self.rng_mode as u8);
+        w.u8(self.quirks.to_bits());
+        match self.speed {
+            Speed::PerFrame(count) => {
+                w.u8(0);
+                w.u32(count);
+            }
+            Speed::Hz(hz) => {
+                w.u8(1);
+                w.u32(hz.to_bits());
+            }
+        }
+        w.bool(self.stack_depth.is_some());
+        w.u32(self.stack_depth.unwrap_or(0) as u32);
+        w.u8(self.layout as u8);
+        w.u16(self.sys.len() as u16);
+        for addr in self.sys.iter() {
+            w.u16(*addr);
+        }
+        w.u32(self.frames.len() as u32);
+        for mask in self.frames.iter() {
+            w.u16(*mask);
+        }
+        w.buf
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, StateError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(StateError::BadVersion(version));
+        }
+        let rom_hash = r.u64()?;
+        let seed = r.u64()?;
+        let rng_mode = match r.u8()? {
+            1 => RngMode::Counter,
+            _ => RngMode::SplitMix,
+        };
+        let quirks = Quirks::from_bits(r.u8()?);
+        let speed = match r.u8()? {
+            1 => Speed::Hz(f32::from_bits(r.u32()?)),
+            _ => Speed::PerFrame(r.u32()?),
+        };
+        let limited = r.bool()?;
+        let depth = r.u32()? as usize;
+        let stack_depth = if limited { Some(depth) } else { None };
+        let layout = match r.u8()? {
+            1 => Layout::Vip,
+            _ => Layout::Separate,
+        };
+        let mut sys = Vec::new();
+        for _ in 0..r.u16()? {
+            sys.push(r.u16()?);
+        }
+        let count = r.u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            frames.push(r.u16()?);
+        }
+
+        Ok(Movie {
+            rom_hash,
+            seed,
+            rng_mode,
+            quirks,
+            speed,
+            stack_depth,
+            layout,
+            sys,
+            frames,
+        })
+    }
+}
+
+//----------------------
+//feeds a movie back in, one frame at a time
+pub struct MoviePlayer {
+    pub movie: Movie,
+    pub frame: usize,
+}
+
+impl MoviePlayer {
+    //sets the machine up the way the recording started. a movie made on another rom or with other
+    //0NNN routines will desync, that is reported as an error but the caller can still choose to
+    //play it
+    pub fn new(movie: Movie, cpu: &mut CPU) -> (MoviePlayer, Result<(), StateError>) {
+        cpu.quirks = movie.quirks;
+        cpu.scheduler = Scheduler::new(movie.speed);
+        cpu.rng = Rng::new(movie.seed);
+        cpu.rng.mode = movie.rng_mode;
+        cpu.mem.stack_depth = movie.stack_depth;
+        cpu.mem.layout = movie.layout;
+
+        let sys = cpu.sys.addresses();
+        let check = if movie.rom_hash != cpu.rom_hash {
+            Err(StateError::RomMismatch(cpu.rom_hash, movie.rom_hash))
+        } else if movie.sys != sys {
+            Err(StateError::SysMismatch(sys, movie.sys.clone()))
+        } else {
+            Ok(())
+        };
+        (MoviePlayer { movie, frame: 0 }, check)
+    }
+
+    //apply the next frame's keys through press/release, false once the movie has run out
+    pub fn next_frame(&mut self, input: &mut Input) -> bool {
+        match self.movie.frames.get(self.frame) {
+            Some(mask) => {
+                input.set_mask(*mask);
+                self.frame += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+//sleep until this close to a frame deadline, then spin the rest. os sleeps overshoot by a
+//millisecond or so, spinning the last bit keeps frames evenly spaced without burning a core
+const SPIN: Duration = Duration::from_millis(2);
+//at most this many frames are run back to back to catch up after a stall
+const MAX_CATCH_UP: u32 = 4;
+//how often the measured speed is reported
+const REPORT_EVERY: Duration = Duration::from_secs(1);
+
+//-----------------------------------------------Pacer---------------------------------------------
+//what the emulator actually managed over the last report period
+#[derive(Copy, Clone, Debug)]
+pub struct SpeedReport {
+    //emulated frames per second
+    pub fps: f64,
+    //fps over the target rate, 1.0 is full speed
+    pub speed: f64,
+    //frames given up on after long stalls
+    pub dropped: u32,
+}
+
+//decides when the frontend should run emulated frames so they come out at the target rate.
+//a short hiccup (a slow frame, the window being dragged) is made up by running a few frames
+//back to back, anything longer is dropped so the game does not fast forward to catch up
+pub struct Pacer {
+    pub frame: Duration,
+    //when the next frame is due
+    next: Instant,
+
+    //frames run and wall time since the last report
+    report_start: Instant,
+    report_frames: u32,
+    //frames skipped because the stall was too long, since the last report
+    dropped: u32,
+}
+
+impl Pacer {
+    //rate is frames per second, 60 for chip8
+    pub fn new(rate: f64) -> Pacer {
+        let now = Instant::now();
+        Pacer {
+            frame: Duration::from_secs_f64(1.0 / rate),
+            next: now,
+            report_start: now,
+            report_frames: 0,
+            dropped: 0,
+        }
+    }
+
+    //how many frames to run right now, 0 if the next one is not due yet
+    pub fn due(&mut self) -> u32 {
+        let now = Instant::now();
+        if now < self.next {
+            return 0;
+        }
+
+        let behind = (now - self.next).as_secs_f64() / self.frame.as_secs_f64();
+        let due = behind as u32 + 1;
+        if due > MAX_CATCH_UP {
+            //too far behind to be worth catching up, run one frame and start counting from now
+            self.dropped += due - 1;
+            self.next = now + self.frame;
+            self.report_frames += 1;
+            return 1;
+        }
+        self.next += self.frame * due;
+        self.report_frames += due;
+        due
+    }
+
+    //block until the next frame is due
+    pub fn wait(&self) {
+        let now = Instant::now();
+        if self.next <= now {
+            return;
+        }
+        let left = self.next - now;
+        if left > SPIN {
+            std::thread::sleep(left - SPIN);
+        }
+        while Instant::now() < self.next {
+            std::hint::spin_loop();
+        }
+    }
+
+    //forget about time spent not running, e.g. in the debugger, instead of treating it as a stall
+    pub fn reset(&mut self) {
+        let now = Instant::now();
+        self.next = now;
+        self.report_start = now;
+        self.report_frames = 0;
+    }
+
+    //measured speed, once every REPORT_EVERY
+    pub fn report(&mut self) -> Option<SpeedReport> {
+        let elapsed = self.report_start.elapsed();
+        if elapsed < REPORT_EVERY {
+            return None;
+        }
+        let fps = self.report_frames as f64 / elapsed.as_secs_f64();
+        let report = SpeedReport {
+            fps,
+            speed: fps * self.frame.as_secs_f64(),
+            dropped: self.dropped,
+        };
+        self.report_start = Instant::now();
+        self.report_frames = 0;
+        self.dropped = 0;
+        Some(report)
+    }
+}
+//-------------------------------------------------------------------------------------------------
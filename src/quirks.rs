@@ -0,0 +1,145 @@
+//-----------------------------------------------Quirks--------------------------------------------
+//the interpreters chip8 programs were written for disagree on a handful of opcodes.
+//each flag here picks one side, profiles bundle the combination a given interpreter used
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quirks {
+    //8XY6/8XYE shift VY into VX (VIP), or shift VX in place and ignore VY (CHIP-48 and later)
+    pub shift_vy: bool,
+    //FX55/FX65 leave I pointing just past the last register copied
+    pub load_store_inc_i: bool,
+    //BNNN is BXNN: jump to XNN + VX instead of NNN + V0
+    pub jump_vx: bool,
+    //8XY1/8XY2/8XY3 reset VF to 0 (VIP side effect of its logic routines)
+    pub vf_reset: bool,
+    //sprites are cut off at the screen edge instead of wrapping around to the other side
+    pub clip: bool,
+    //DXYN waits for the next 60Hz vblank before drawing, limiting a program to one draw per frame
+    pub display_wait: bool,
+    //FX0A finishes as soon as a key goes down instead of waiting for it to be released like the
+    //VIP did, for interpreters that finish on press
+    pub key_press: bool,
+}
+
+//named quirk combinations for the interpreters people actually wrote roms for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Profile {
+    Vip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Profile {
+    pub fn from_name(name: &str) -> Option<Profile> {
+        match name.to_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Some(Profile::Vip),
+            "chip48" | "chip-48" => Some(Profile::Chip48),
+            "schip" | "superchip" | "super-chip" => Some(Profile::SuperChip),
+            "xochip" | "xo-chip" => Some(Profile::XoChip),
+            _ => None,
+        }
+    }
+
+    //how deep CALLs could nest on the original interpreter
+    pub fn stack_depth(&self) -> usize {
+        match self {
+            Profile::Vip => 12,
+            Profile::Chip48 | Profile::SuperChip | Profile::XoChip => 16,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn new(profile: Profile) -> Quirks {
+        match profile {
+            Profile::Vip => Quirks {
+                shift_vy: true,
+                load_store_inc_i: true,
+                jump_vx: false,
+                vf_reset: true,
+                clip: true,
+                display_wait: true,
+                key_press: false,
+            },
+            Profile::Chip48 => Quirks {
+                shift_vy: false,
+                load_store_inc_i: false,
+                jump_vx: true,
+                vf_reset: false,
+                clip: true,
+                display_wait: false,
+                key_press: false,
+            },
+            Profile::SuperChip => Quirks {
+                shift_vy: false,
+                load_store_inc_i: false,
+                jump_vx: true,
+                vf_reset: false,
+                clip: true,
+                display_wait: false,
+                key_press: false,
+            },
+            Profile::XoChip => Quirks {
+                shift_vy: true,
+                load_store_inc_i: true,
+                jump_vx: false,
+                vf_reset: false,
+                clip: false,
+                display_wait: false,
+                key_press: false,
+            },
+        }
+    }
+
+    //override a single flag by name, value is on/off, true/false or 1/0
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let value = match value.to_lowercase().as_str() {
+            "on" | "true" | "1" | "yes" => true,
+            "off" | "false" | "0" | "no" => false,
+            _ => return Err(format!("bad value for quirk {}: {}", name, value)),
+        };
+
+        match name.to_lowercase().as_str() {
+            "shift" => self.shift_vy = value,
+            "memory" | "loadstore" => self.load_store_inc_i = value,
+            "jump" => self.jump_vx = value,
+            "vfreset" | "logic" => self.vf_reset = value,
+            "clip" => self.clip = value,
+            "vblank" | "displaywait" => self.display_wait = value,
+            "keypress" | "fx0a" => self.key_press = value,
+            _ => return Err(format!("unknown quirk: {}", name)),
+        }
+        Ok(())
+    }
+
+    //packed form for file headers, one bit per flag in declaration order
+    pub fn to_bits(&self) -> u8 {
+        (self.shift_vy as u8)
+            | (self.load_store_inc_i as u8) << 1
+            | (self.jump_vx as u8) << 2
+            | (self.vf_reset as u8) << 3
+            | (self.clip as u8) << 4
+            | (self.display_wait as u8) << 5
+            | (self.key_press as u8) << 6
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_vy: bits & 1 != 0,
+            load_store_inc_i: bits & (1 << 1) != 0,
+            jump_vx: bits & (1 << 2) != 0,
+            vf_reset: bits & (1 << 3) != 0,
+            clip: bits & (1 << 4) != 0,
+            display_wait: bits & (1 << 5) != 0,
+            key_press: bits & (1 << 6) != 0,
+        }
+    }
+}
+
+//what rust-8 has always done, which happens to line up with xo-chip
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::new(Profile::XoChip)
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use crate::cpu::CPU;
+
+//-----------------------------------------------Rewind--------------------------------------------
+//ring buffer of recent frames for hold-to-rewind.
+//only the newest save state is kept whole, every older frame is stored as the xor between it and
+//the frame after it, run length encoded. from one frame to the next almost nothing in the 64KB of
+//memory or the framebuffer changes, so a frame typically costs tens of bytes
+pub struct Rewind {
+    //how many frames of history to keep
+    pub capacity: usize,
+    //newest first is at the back, each entry turns the state after it into the one before it
+    deltas: VecDeque<Vec<u8>>,
+    //full state of the most recently pushed frame
+    current: Vec<u8>,
+    //total size of deltas, for reporting
+    pub bytes: usize,
+}
+
+impl Rewind {
+    //frames is the history length, 60 per second of play
+    pub fn new(frames: usize) -> Rewind {
+        Rewind {
+            capacity: frames,
+            deltas: VecDeque::new(),
+            current: Vec::new(),
+            bytes: 0,
+        }
+    }
+
+    //record the machine as it is now, call once per frame
+    pub fn push(&mut self, cpu: &CPU) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let state = cpu.save_state();
+        if !self.current.is_empty() {
+            let delta = encode(&self.current, &state);
+            self.bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.current = state;
+
+        while self.deltas.len() > self.capacity {
+            if let Some(old) = self.deltas.pop_front() {
+                self.bytes -= old.len();
+            }
+        }
+    }
+
+    //step the machine one recorded frame back, false once history runs out
+    pub fn rewind(&mut self, cpu: &mut CPU) -> bool {
+        let delta = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        self.bytes -= delta.len();
+        decode(&mut self.current, &delta);
+
+        //states in here all came from this machine, so loading can only fail if the rom changed
+        cpu.load_state(&self.current).is_ok()
+    }
+
+    //frames of history currently held
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.current.clear();
+        self.bytes = 0;
+    }
+}
+
+//----------------------
+//delta format: length of the older state, then repeated (unchanged byte count, changed byte count,
+//changed bytes xored), all counts as LEB128 varints. states grow and shrink with the call stack,
+//the shorter one is treated as padded with zeros
+fn encode(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let byte = |state: &[u8], i: usize| state.get(i).copied().unwrap_or(0);
+    let len = old.len().max(new.len());
+
+    let mut out = Vec::new();
+    push_varint(&mut out, old.len());
+    let mut i = 0;
+    while i < len {
+        let skip_start = i;
+        while i < len && byte(old, i) == byte(new, i) {
+            i += 1;
+        }
+        let diff_start = i;
+        while i < len && byte(old, i) != byte(new, i) {
+            i += 1;
+        }
+        if diff_start == i {
+            break;
+        }
+
+        push_varint(&mut out, diff_start - skip_start);
+        push_varint(&mut out, i - diff_start);
+        out.extend((diff_start..i).map(|i| byte(old, i) ^ byte(new, i)));
+    }
+    out
+}
+
+//xor a delta into the newer state, turning it back into the older one
+fn decode(state: &mut Vec<u8>, delta: &[u8]) {
+    let mut i = 0;
+    let old_len = read_varint(delta, &mut i);
+    if state.len() < old_len {
+        state.resize(old_len, 0);
+    }
+
+    let mut pos = 0;
+    while i < delta.len() {
+        pos += read_varint(delta, &mut i);
+        let len = read_varint(delta, &mut i);
+        for byte in state[pos..pos + len].iter_mut() {
+            *byte ^= delta[i];
+            i += 1;
+        }
+        pos += len;
+    }
+    state.truncate(old_len);
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*i];
+        *i += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+//-------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec_round_trip() {
+        let old: Vec<u8> = (0..300).map(|i| (i * 7) as u8).collect();
+        let mut same_len = old.clone();
+        same_len[0] ^= 1;
+        same_len[150] = 0;
+        same_len[299] = 0xFF;
+        let longer: Vec<u8> = old.iter().copied().chain([1, 0, 2]).collect();
+        let shorter = old[..200].to_vec();
+
+        for new in [old.clone(), same_len, longer, shorter, Vec::new()] {
+            let mut state = new.clone();
+            decode(&mut state, &encode(&old, &new));
+            assert_eq!(state, old);
+        }
+    }
+
+    #[test]
+    fn unchanged_frames_are_small() {
+        let state = CPU::default().save_state();
+        assert!(encode(&state, &state).len() <= 3);
+    }
+
+    #[test]
+    fn rewinds_through_calls_and_returns() {
+        let mut cpu = CPU::default();
+        let mut rewind = Rewind::new(10);
+        let mut states = Vec::new();
+
+        //the call stack grows and shrinks, so the states change length from frame to frame
+        for frame in 0..6u16 {
+            cpu.pc = 0x200 + frame * 2;
+            cpu.mem.v_regs[0] = frame as u8;
+            cpu.disp
+                .draw_sprite(1, &[0x80], false, frame as u8, 0, false);
+            if frame < 3 {
+                cpu.mem.push_return(0x300 + frame);
+            } else {
+                cpu.mem.pop_return();
+            }
+            states.push(cpu.save_state());
+            rewind.push(&cpu);
+        }
+        assert_eq!(rewind.len(), 5);
+
+        for expected in states.iter().rev().skip(1) {
+            assert!(rewind.rewind(&mut cpu));
+            assert_eq!(&cpu.save_state(), expected);
+        }
+        assert!(!rewind.rewind(&mut cpu));
+        assert_eq!(rewind.bytes, 0);
+    }
+
+    #[test]
+    fn keeps_only_capacity_frames() {
+        let mut cpu = CPU::default();
+        let mut rewind = Rewind::new(3);
+        for frame in 0..10 {
+            cpu.mem.v_regs[1] = frame;
+            rewind.push(&cpu);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        while rewind.rewind(&mut cpu) {}
+        assert_eq!(cpu.mem.v_regs[1], 6);
+
+        let mut off = Rewind::new(0);
+        off.push(&cpu);
+        off.push(&cpu);
+        assert!(off.is_empty());
+    }
+}
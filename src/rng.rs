@@ -0,0 +1,98 @@
+//bytes the counter generator picks from, a fixed shuffle of 0-255
+const TABLE: [u8; 256] = table();
+
+const fn table() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        //odd multiplier plus offset, then a rotate: every byte shows up exactly once
+        table[i] = ((i * 167 + 91) as u8).rotate_left(3);
+        i += 1;
+    }
+    table
+}
+
+//-----------------------------------------------RNG-----------------------------------------------
+//random numbers for CXNN, owned by the emulator so a run can be reproduced from its seed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RngMode {
+    //splitmix64, good quality and fully determined by the seed
+    SplitMix,
+    //a counter picking bytes from a table, cheap like an 8-bit interpreter's, see Rng::counter_byte
+    Counter,
+}
+
+impl RngMode {
+    pub fn from_name(name: &str) -> Option<RngMode> {
+        match name.to_lowercase().as_str() {
+            "splitmix" | "default" => Some(RngMode::SplitMix),
+            "counter" | "vip" => Some(RngMode::Counter),
+            _ => None,
+        }
+    }
+}
+
+pub struct Rng {
+    pub mode: RngMode,
+    //what the generator was started from, report this to reproduce a run
+    pub seed: u64,
+    pub state: u64,
+    //counter mode: bumped every instruction, low byte picks from the table, high byte is the
+    //last result
+    pub r9: u16,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            mode: RngMode::SplitMix,
+            seed,
+            state: seed,
+            r9: seed as u16,
+        }
+    }
+
+    //pick a seed from the os, for runs where nobody asked for one
+    pub fn random_seed() -> u64 {
+        rand::random()
+    }
+
+    //advance once per executed instruction, only the counter generator cares
+    pub fn clock(&mut self) {
+        self.r9 = self.r9.wrapping_add(1);
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self.mode {
+            RngMode::SplitMix => self.splitmix() as u8,
+            RngMode::Counter => self.counter_byte(),
+        }
+    }
+
+    fn splitmix(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    //bump the counter, add the table byte its low half points at to the last result and keep
+    //the sum as the new result. modelled on the VIP's routine, but the VIP read its own interpreter
+    //code as the table, so the numbers are not the ones a VIP would give
+    fn counter_byte(&mut self) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let lo = self.r9 & 0xFF;
+        let hi = (self.r9 >> 8) as u8;
+        let value = TABLE[lo as usize].wrapping_add(hi);
+        self.r9 = (value as u16) << 8 | lo;
+        value
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new(Rng::random_seed())
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -1,21 +1,26 @@
-pub use std::fs;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+
+use crate::error::EmuError;
 //---------------------------------------------ROM---------------------------------------------
 pub struct ROM {
     pub buffer: Vec<u8>,
 }
 
 impl ROM {
-    pub fn new(filename: &str) -> ROM {
-        let mut f = File::open(&filename).expect("file not found");
-        let metadata = fs::metadata(&filename).expect("unable to read metadata");
-        let mut buffer = vec![0; metadata.len() as usize];
-        f.read_exact(&mut buffer).expect("buffer overflow");
+    pub fn new(filename: &str) -> Result<ROM, EmuError> {
+        let buffer = fs::read(filename)?;
+
+        Ok(ROM { buffer })
+    }
 
-        ROM { buffer }
-        //DEBUG: print vec as bytes
-        //println!("{:#04x?}", buffer);
+    //64 bit FNV-1a of the rom contents, identifies which game a save state or movie belongs to
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in self.buffer.iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
     }
 }
 
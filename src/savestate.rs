@@ -0,0 +1,500 @@
+use std::fmt;
+
+use crate::cpu::{KeyWait, CPU};
+use crate::display::Display;
+use crate::input::Input;
+use crate::memory::Memory;
+use crate::rng::{Rng, RngMode};
+use crate::scheduler::Scheduler;
+use crate::sound::Sound;
+
+//every state file starts with these 4 bytes, then the format version and the rom hash
+pub const MAGIC: &[u8; 4] = b"R8ST";
+//bump whenever the layout below changes, old states are refused instead of misread
+pub const VERSION: u16 = 7;
+
+//-------------------------------------------Save States-------------------------------------------
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    //not a rust-8 state at all
+    BadMagic,
+    //written by a different version of the format
+    BadVersion(u16),
+    //state belongs to another rom, (expected, found)
+    RomMismatch(u64, u64),
+    //ran out of data partway through
+    Truncated,
+    //movie was recorded with other 0NNN routines than this machine has, (expected, found)
+    SysMismatch(Vec<u16>, Vec<u16>),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a rust-8 save state"),
+            StateError::BadVersion(v) => {
+                write!(f, "save state version {} (expected {})", v, VERSION)
+            }
+            StateError::RomMismatch(expected, found) => write!(
+                f,
+                "save state is for rom {:016x}, this is {:016x}",
+                found, expected
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::SysMismatch(expected, found) => write!(
+                f,
+                "recorded with 0NNN routines at {:03x?}, this machine has {:03x?} (see --sys)",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+//----------------------
+//little endian byte sink
+pub struct StateWriter {
+    pub buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { buf: Vec::new() }
+    }
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+    pub fn bool(&mut self, v: bool) {
+        self.buf.push(v as u8);
+    }
+    pub fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn u128(&mut self, v: u128) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+//reads back what StateWriter wrote, in the same order
+#[derive(Clone)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, pos: 0 }
+    }
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos + len;
+        let slice = self.data.get(self.pos..end).ok_or(StateError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+    //true once every byte has been read
+    pub fn finished(&self) -> bool {
+        self.pos == self.data.len()
+    }
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.u8()? != 0)
+    }
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let mut raw = [0; 2];
+        raw.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(raw))
+    }
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut raw = [0; 4];
+        raw.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(raw))
+    }
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut raw = [0; 8];
+        raw.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+    pub fn u128(&mut self) -> Result<u128, StateError> {
+        let mut raw = [0; 16];
+        raw.copy_from_slice(self.bytes(16)?);
+        Ok(u128::from_le_bytes(raw))
+    }
+}
+
+//----------------------
+//anything that is part of the machine state
+pub trait Snapshot {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+impl Snapshot for Memory {
+    fn save(&self, w: &mut StateWriter) {
+        w.bytes(&self.mem);
+        w.bytes(&self.v_regs);
+        w.u16(self.I);
+        w.u16(self.stack.len() as u16);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.bytes(&self.rpl);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let len = self.mem.len();
+        self.mem.copy_from_slice(r.bytes(len)?);
+        self.v_regs.copy_from_slice(r.bytes(16)?);
+        self.I = r.u16()?;
+        let depth = r.u16()?;
+        self.stack.clear();
+        for _ in 0..depth {
+            self.stack.push(r.u16()?);
+        }
+        self.rpl.copy_from_slice(r.bytes(16)?);
+        Ok(())
+    }
+}
+
+impl Snapshot for Display {
+    fn save(&self, w: &mut StateWriter) {
+        w.bool(self.hires);
+        w.u8(self.planes);
+        for plane in self.rows.iter() {
+            for row in plane.iter() {
+                w.u128(*row);
+            }
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.hires = r.bool()?;
+        self.planes = r.u8()?;
+        for plane in self.rows.iter_mut() {
+            for row in plane.iter_mut() {
+                *row = r.u128()?;
+            }
+        }
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+impl Snapshot for Sound {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(self.dt);
+        w.u8(self.st);
+        w.bytes(&self.pattern);
+        w.bool(self.pattern_loaded);
+        w.u8(self.pitch);
+        w.u64(self.phase.to_bits());
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.dt = r.u8()?;
+        self.st = r.u8()?;
+        self.pattern.copy_from_slice(r.bytes(16)?);
+        self.pattern_loaded = r.bool()?;
+        self.pitch = r.u8()?;
+        self.phase = f64::from_bits(r.u64()?);
+        Ok(())
+    }
+}
+
+impl Snapshot for Input {
+    fn save(&self, w: &mut StateWriter) {
+        for key in self.keys.iter() {
+            w.bool(*key);
+        }
+        w.u16(self.pressed);
+        w.u16(self.released);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for key in self.keys.iter_mut() {
+            *key = r.bool()?;
+        }
+        self.pressed = r.u16()?;
+        self.released = r.u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Rng {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(self.mode as u8);
+        w.u64(self.seed);
+        w.u64(self.state);
+        w.u16(self.r9);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.mode = match r.u8()? {
+            1 => RngMode::Counter,
+            _ => RngMode::SplitMix,
+        };
+        self.seed = r.u64()?;
+        self.state = r.u64()?;
+        self.r9 = r.u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Scheduler {
+    fn save(&self, w: &mut StateWriter) {
+        w.bool(self.pending.is_some());
+        w.u32(self.pending.unwrap_or(0));
+        w.u64(self.carry.to_bits());
+        w.u64(self.frame);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let in_frame = r.bool()?;
+        let pending = r.u32()?;
+        self.pending = if in_frame { Some(pending) } else { None };
+        self.carry = f64::from_bits(r.u64()?);
+        self.frame = r.u64()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for CPU {
+    fn save(&self, w: &mut StateWriter) {
+        w.u16(self.pc);
+        w.bool(self.vblank);
+        w.bool(self.exited);
+        w.bool(self.key_wait.is_some());
+        if let Some(wait) = self.key_wait {
+            w.u8(wait.reg);
+            w.bool(wait.key.is_some());
+            w.u8(wait.key.unwrap_or(0));
+        }
+        self.mem.save(w);
+        self.disp.save(w);
+        self.sound.save(w);
+        self.input.save(w);
+        self.rng.save(w);
+        self.scheduler.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pc = r.u16()?;
+        self.vblank = r.bool()?;
+        self.exited = r.bool()?;
+        self.key_wait = None;
+        if r.bool()? {
+            let reg = r.u8()?;
+            let has_key = r.bool()?;
+            let key = r.u8()?;
+            self.key_wait = Some(KeyWait {
+                reg,
+                key: if has_key { Some(key) } else { None },
+            });
+        }
+        self.mem.load(r)?;
+        self.disp.load(r)?;
+        self.sound.load(r)?;
+        self.input.load(r)?;
+        self.rng.load(r)?;
+        self.scheduler.load(r)?;
+        Ok(())
+    }
+}
+//----------------------
+
+impl CPU {
+    //whole machine as a self describing blob: header, then every component in order
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w.u64(self.rom_hash);
+        self.save(&mut w);
+        w.buf
+    }
+
+    //checks the header and size before touching anything, so a bad state leaves the machine alone
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(StateError::BadVersion(version));
+        }
+        let hash = r.u64()?;
+        if hash != self.rom_hash {
+            return Err(StateError::RomMismatch(self.rom_hash, hash));
+        }
+        //the stack makes states vary in size, so read it into a throwaway machine first and only
+        //load for real once the whole thing parsed and nothing was left over
+        let mut scratch = CPU::default();
+        let mut check = r.clone();
+        scratch.load(&mut check)?;
+        if !check.finished() {
+            return Err(StateError::Truncated);
+        }
+        self.load(&mut r)
+    }
+}
+//-------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::KeyWait;
+    use crate::memory::Layout;
+    use crate::movie::{Movie, MoviePlayer};
+    use crate::quirks::{Profile, Quirks};
+    use crate::scheduler::Speed;
+    use crate::sys::SysCalls;
+
+    fn blank() -> CPU {
+        CPU {
+            rom_hash: 0x1234,
+            ..CPU::default()
+        }
+    }
+
+    //a machine with something non default in every component
+    fn busy() -> CPU {
+        let mut cpu = blank();
+        cpu.pc = 0x2A4;
+        cpu.mem.v_regs[3] = 0x42;
+        cpu.mem.I = 0x300;
+        cpu.mem.mem[0x300] = 0xAB;
+        cpu.mem.push_return(0x202);
+        cpu.mem.push_return(0x250);
+        cpu.mem.rpl[1] = 7;
+        cpu.disp.set_hires(true);
+        cpu.disp.planes = 3;
+        cpu.disp
+            .draw_sprite(2, &[0xF0, 0x0F], false, 100, 62, false);
+        cpu.sound.dt = 30;
+        cpu.sound.st = 5;
+        cpu.input.press(0xC);
+        cpu.key_wait = Some(KeyWait {
+            reg: 4,
+            key: Some(0xC),
+        });
+        cpu.rng.next_byte();
+        cpu.scheduler.frame = 99;
+        cpu
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = busy();
+        let state = cpu.save_state();
+
+        let mut loaded = blank();
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.save_state(), state);
+        assert_eq!(loaded.pc, 0x2A4);
+        assert_eq!(loaded.mem.stack, vec![0x202, 0x250]);
+        assert_eq!(loaded.disp.rows, cpu.disp.rows);
+        assert_eq!(loaded.key_wait, cpu.key_wait);
+        assert_eq!(loaded.rng.next_byte(), cpu.rng.next_byte());
+    }
+
+    #[test]
+    fn bad_states_leave_the_machine_alone() {
+        let state = busy().save_state();
+        let mut cpu = blank();
+        let before = cpu.save_state();
+
+        let mut other_rom = busy();
+        other_rom.rom_hash = 0x9999;
+        assert_eq!(
+            cpu.load_state(&other_rom.save_state()),
+            Err(StateError::RomMismatch(0x1234, 0x9999))
+        );
+
+        let mut old_version = state.clone();
+        old_version[4] = old_version[4].wrapping_sub(1);
+        assert_eq!(
+            cpu.load_state(&old_version),
+            Err(StateError::BadVersion(VERSION - 1))
+        );
+
+        assert_eq!(cpu.load_state(b"nope"), Err(StateError::BadMagic));
+        assert_eq!(
+            cpu.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        let mut trailing = state.clone();
+        trailing.push(0);
+        assert_eq!(cpu.load_state(&trailing), Err(StateError::Truncated));
+
+        assert_eq!(cpu.save_state(), before);
+    }
+
+    //movies are written with the same writer as states
+    #[test]
+    fn movie_round_trip() {
+        let mut cpu = blank();
+        cpu.rng = Rng::new(77);
+        cpu.rng.mode = RngMode::Counter;
+        cpu.quirks = Quirks::new(Profile::Vip);
+        cpu.scheduler.speed = Speed::Hz(720.0);
+        cpu.mem.stack_depth = None;
+        cpu.mem.layout = Layout::Vip;
+        cpu.sys = SysCalls::vip();
+        let mut movie = Movie::new(&cpu);
+        for mask in [0x0000, 0x0020, 0x0021, 0x8000] {
+            cpu.input.set_mask(mask);
+            movie.record(&cpu.input);
+        }
+
+        let played = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(played.frames, vec![0x0000, 0x0020, 0x0021, 0x8000]);
+
+        //playing it back on a default machine brings everything over but the 0NNN routines
+        let mut fresh = blank();
+        let (mut player, check) = MoviePlayer::new(played, &mut fresh);
+        assert_eq!(
+            check,
+            Err(StateError::SysMismatch(
+                Vec::new(),
+                SysCalls::vip().addresses()
+            ))
+        );
+        assert_eq!(fresh.rng.seed, 77);
+        assert_eq!(fresh.rng.mode, RngMode::Counter);
+        assert_eq!(fresh.quirks, cpu.quirks);
+        assert_eq!(fresh.scheduler.speed, Speed::Hz(720.0));
+        assert_eq!(fresh.mem.stack_depth, None);
+        assert_eq!(fresh.mem.layout, Layout::Vip);
+
+        let mut masks = Vec::new();
+        while player.next_frame(&mut fresh.input) {
+            masks.push(fresh.input.mask());
+        }
+        assert_eq!(masks, movie.frames);
+        assert!(player.finished());
+
+        assert_eq!(
+            Movie::from_bytes(&movie.to_bytes()[..20]).err(),
+            Some(StateError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use crate::cpu::CPU;
+use crate::error::EmuError;
+
+//the timers, the display and audio all run on the VIP's 60Hz vertical blank
+pub const FRAME_RATE: f64 = 60.0;
+
+//-----------------------------------------------Scheduler-----------------------------------------
+//how fast the cpu runs, counted against the 60Hz frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    //the same number of instructions every frame, what most modern interpreters do
+    PerFrame(u32),
+    //instructions per second, spread over the frames with the fraction carried to the next one
+    Hz(f32),
+}
+
+//splits emulated time into frames: a batch of instructions, then one timer tick and one frame of
+//audio. the frontend decides when a frame happens, nothing in here looks at the wall clock
+pub struct Scheduler {
+    pub speed: Speed,
+    //instructions left in the frame being run, None between frames
+    pub pending: Option<u32>,
+    //Hz mode: part of an instruction owed from earlier frames
+    pub carry: f64,
+    //frames completed since power on
+    pub frame: u64,
+}
+
+impl Scheduler {
+    pub fn new(speed: Speed) -> Scheduler {
+        Scheduler {
+            speed,
+            pending: None,
+            carry: 0.0,
+            frame: 0,
+        }
+    }
+
+    //how many instructions the next frame gets
+    fn budget(&mut self) -> u32 {
+        match self.speed {
+            Speed::PerFrame(count) => count,
+            Speed::Hz(hz) => {
+                let exact = self.carry + hz as f64 / FRAME_RATE;
+                let count = exact.floor();
+                self.carry = exact - count;
+                count as u32
+            }
+        }
+    }
+}
+
+//500 instructions a second is what rust-8 has always run at
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new(Speed::Hz(500.0))
+    }
+}
+
+impl CPU {
+    //run one emulated frame. on an error the frame is left half done with pc on the faulting
+    //instruction, calling this again picks up where it stopped
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        while !self.step_frame()? {}
+        Ok(())
+    }
+
+    //run a single instruction of the current frame, starting a new one first if needed.
+    //true once the frame is over: timers ticked and audio rendered
+    pub fn step_frame(&mut self) -> Result<bool, EmuError> {
+        let pending = match self.scheduler.pending {
+            Some(pending) => pending,
+            None => {
+                self.vblank();
+                self.scheduler.budget()
+            }
+        };
+        self.scheduler.pending = Some(pending);
+
+        if pending > 0 && !self.exited {
+            self.step()?;
+            self.scheduler.pending = Some(pending - 1);
+            if pending > 1 && !self.exited {
+                return Ok(false);
+            }
+        }
+
+        self.scheduler.pending = None;
+        self.scheduler.frame += 1;
+        self.sound.tick();
+        Ok(true)
+    }
+
+    //step over the instruction at pc without running it, for moving past a bad opcode. it still
+    //counts against the frame, so a program run off into empty memory cannot skip forever
+    pub fn skip_instruction(&mut self) {
+        self.pc = self.pc.wrapping_add(2);
+        if let Some(pending) = self.scheduler.pending {
+            self.scheduler.pending = Some(pending.saturating_sub(1));
+        }
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,99 @@
+use sdl2::pixels::Color;
+
+use rust_8::display::{Display, MAX_HEIGHT, MAX_WIDTH};
+
+fn rgb(colour: Color) -> [f32; 3] {
+    [colour.r as f32, colour.g as f32, colour.b as f32]
+}
+
+fn to_colour(rgb: [f32; 3]) -> Color {
+    Color::RGB(rgb[0] as u8, rgb[1] as u8, rgb[2] as u8)
+}
+
+//amount of the way from a to b
+fn mix(a: [f32; 3], b: [f32; 3], amount: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * amount,
+        a[1] + (b[1] - a[1]) * amount,
+        a[2] + (b[2] - a[2]) * amount,
+    ]
+}
+
+//------------------------------------------------Afterglow----------------------------------------
+//chip8 games move sprites by XORing them off and back on, so anything that moves spends some
+//frames not drawn at all and flickers. these filters hide that in the picture only, the emulated
+//framebuffer is never touched:
+//  phosphor: pixels that go dark fade out over a few frames like on a crt
+//  blend: pixels lit in the frame before this one are still shown, ORing the last two frames
+pub struct Afterglow {
+    //share of the old colour a dark pixel keeps every frame, 0 is off
+    pub phosphor: f32,
+    //how strongly last frame's pixels show through, 0 is off and 1 is a plain OR
+    pub blend: f32,
+    //the framebuffer as it was last frame, hires says which mode that was in
+    last: Display,
+    //colour every pixel was shown in last frame
+    glow: Vec<[f32; 3]>,
+    //false until a frame has been shown
+    primed: bool,
+}
+
+impl Afterglow {
+    pub fn new(phosphor: f32, blend: f32) -> Afterglow {
+        Afterglow {
+            phosphor,
+            blend,
+            last: Display::new(),
+            glow: vec![[0.0; 3]; MAX_WIDTH * MAX_HEIGHT],
+            primed: false,
+        }
+    }
+
+    //fading needs a fresh picture every frame, not just when the framebuffer changes
+    pub fn active(&self) -> bool {
+        self.phosphor > 0.0 || self.blend > 0.0
+    }
+
+    //move the fade on by one emulated frame, however often the picture is drawn in between.
+    //call after every frame the cpu runs
+    pub fn step(&mut self, disp: &Display, colours: &[Color]) {
+        if !self.active() {
+            return;
+        }
+        //after a mode switch the old pixels are in different places, so there is no history
+        let history = self.primed && self.last.hires == disp.hires;
+        for y in 0..disp.height() {
+            for x in 0..disp.width() {
+                let now = disp.pixel(x, y);
+                let mut colour = rgb(colours[now as usize]);
+                let glow = &mut self.glow[y * MAX_WIDTH + x];
+                //pixels that are lit are shown as they are, only dark ones get history mixed in
+                if history && now == 0 {
+                    if self.blend > 0.0 {
+                        let before = self.last.pixel(x, y);
+                        if before != 0 {
+                            colour = mix(colour, rgb(colours[before as usize]), self.blend);
+                        }
+                    }
+                    if self.phosphor > 0.0 {
+                        colour = mix(colour, *glow, self.phosphor);
+                    }
+                }
+                *glow = colour;
+            }
+        }
+        self.primed = true;
+        self.last.rows = disp.rows;
+        self.last.hires = disp.hires;
+    }
+
+    //colour to show for the pixel at x, y as of the last step, plain palette colours until the
+    //first one
+    pub fn colour(&self, disp: &Display, colours: &[Color], x: usize, y: usize) -> Color {
+        if !self.primed || self.last.hires != disp.hires {
+            return colours[disp.pixel(x, y) as usize];
+        }
+        to_colour(self.glow[y * MAX_WIDTH + x])
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,50 @@
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::Sdl;
+
+use rust_8::frontend::Audio;
+use rust_8::sound::Sound;
+
+//if the device falls this far behind (fast forward) the backlog is thrown away, in seconds
+const MAX_LATENCY: f32 = 0.25;
+
+//-----------------------------------------------SDL Audio-----------------------------------------
+//plays whatever the core rendered for each emulated frame, in order
+pub struct SdlAudio {
+    pub queue: AudioQueue<f32>,
+    pub sample_rate: u32,
+}
+
+impl SdlAudio {
+    pub fn new(sdl_context: &Sdl) -> SdlAudio {
+        let audio_subsystem = sdl_context.audio().unwrap();
+        let desired = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem
+            .open_queue::<f32, _>(None, &desired)
+            .unwrap();
+        let sample_rate = queue.spec().freq as u32;
+        queue.resume();
+
+        SdlAudio { queue, sample_rate }
+    }
+}
+
+impl Audio for SdlAudio {
+    fn update(&mut self, sound: &mut Sound) {
+        sound.sample_rate = self.sample_rate;
+        let samples = sound.take_samples();
+        if samples.is_empty() {
+            return;
+        }
+
+        let queued = self.queue.size() as usize / std::mem::size_of::<f32>();
+        if queued as f32 > self.sample_rate as f32 * MAX_LATENCY {
+            self.queue.clear();
+        }
+        self.queue.queue(&samples);
+    }
+}
+//-------------------------------------------------------------------------------------------------
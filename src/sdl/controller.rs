@@ -0,0 +1,203 @@
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::{GameControllerSubsystem, Sdl};
+
+use rust_8::frontend::InputSource;
+use rust_8::input::Input;
+
+//how hard the pad shakes while the sound timer runs, and how long one rumble lasts. it is
+//renewed every frame, so it stops within this long of the timer running out even if nobody
+//tells it to
+const RUMBLE_STRENGTH: u16 = 0x6000;
+const RUMBLE_MS: u32 = 100;
+
+//button layouts for some well known roms, picked by file name when no --pad is given.
+//the default suits most games: d-pad on 5/7/8/9 (up/left/down/right), A on 6, B on 4
+const PRESETS: &[(&str, &[(Button, u8)])] = &[
+    (
+        "default",
+        &[
+            (Button::DPadUp, 0x5),
+            (Button::DPadLeft, 0x7),
+            (Button::DPadDown, 0x8),
+            (Button::DPadRight, 0x9),
+            (Button::A, 0x6),
+            (Button::B, 0x4),
+            (Button::X, 0x1),
+            (Button::Y, 0x2),
+            (Button::Start, 0xF),
+        ],
+    ),
+    //left paddle on 1/4, right paddle on C/D from the shoulders
+    (
+        "pong",
+        &[
+            (Button::DPadUp, 0x1),
+            (Button::DPadDown, 0x4),
+            (Button::LeftShoulder, 0xC),
+            (Button::RightShoulder, 0xD),
+        ],
+    ),
+    (
+        "tetris",
+        &[
+            (Button::DPadLeft, 0x5),
+            (Button::DPadRight, 0x6),
+            (Button::DPadDown, 0x7),
+            (Button::A, 0x4),
+        ],
+    ),
+    (
+        "invaders",
+        &[
+            (Button::DPadLeft, 0x4),
+            (Button::DPadRight, 0x6),
+            (Button::A, 0x5),
+        ],
+    ),
+    ("brix", &[(Button::DPadLeft, 0x4), (Button::DPadRight, 0x6)]),
+    (
+        "breakout",
+        &[(Button::DPadLeft, 0x4), (Button::DPadRight, 0x6)],
+    ),
+];
+
+//-----------------------------------------------SDL Controller------------------------------------
+//game controllers, opened and closed as they are plugged in and out. every pad drives the same
+//keypad through one shared set of bindings
+pub struct SdlController {
+    subsystem: GameControllerSubsystem,
+    pads: Vec<GameController>,
+    //(button, chip8 key)
+    pub bindings: Vec<(Button, u8)>,
+    //(pad instance id, button) for every bound button held right now
+    down: Vec<(u32, Button)>,
+    //shake the pads while the sound timer runs
+    pub rumble: bool,
+    rumbling: bool,
+}
+
+impl SdlController {
+    //None when sdl has no controller support, the keyboard still works then
+    pub fn new(
+        sdl_context: &Sdl,
+        bindings: Vec<(Button, u8)>,
+        rumble: bool,
+    ) -> Option<SdlController> {
+        let subsystem = sdl_context.game_controller().ok()?;
+        //pads already plugged in show up as ControllerDeviceAdded events too
+        Some(SdlController {
+            subsystem,
+            pads: Vec::new(),
+            bindings,
+            down: Vec::new(),
+            rumble,
+            rumbling: false,
+        })
+    }
+
+    pub fn preset(name: &str) -> Option<Vec<(Button, u8)>> {
+        let name = name.to_lowercase();
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, bindings)| bindings.to_vec())
+    }
+
+    //preset by name, otherwise the one named after the rom if there is one, otherwise default.
+    //buttons are sdl names (a, b, x, y, dpup, dpleft, start, leftshoulder...) bound on top
+    pub fn configure(
+        preset: Option<&str>,
+        rom: &str,
+        buttons: &[(String, u8)],
+    ) -> Result<Vec<(Button, u8)>, String> {
+        let mut bindings = match preset {
+            Some(name) => {
+                SdlController::preset(name).ok_or(format!("unknown pad layout: {}", name))?
+            }
+            None => {
+                let stem = std::path::Path::new(rom)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                SdlController::preset(&stem)
+                    .or_else(|| SdlController::preset("default"))
+                    .unwrap_or_default()
+            }
+        };
+
+        for (name, key) in buttons.iter() {
+            let button =
+                Button::from_string(name.trim()).ok_or(format!("unknown button: {}", name))?;
+            bindings.retain(|(bound, _)| *bound != button);
+            bindings.push((button, *key));
+        }
+        Ok(bindings)
+    }
+
+    fn key_for(&self, button: Button) -> Option<u8> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == button)
+            .map(|(_, key)| *key)
+    }
+
+    //call once per frame with the sound timer
+    pub fn update_rumble(&mut self, sound_on: bool) {
+        if !self.rumble || (!sound_on && !self.rumbling) {
+            return;
+        }
+        let (strength, ms) = if sound_on {
+            (RUMBLE_STRENGTH, RUMBLE_MS)
+        } else {
+            (0, 0)
+        };
+        for pad in self.pads.iter_mut() {
+            //pads without motors just say no, that is fine
+            let _ = pad.set_rumble(strength, strength, ms);
+        }
+        self.rumbling = sound_on;
+    }
+}
+
+impl InputSource for SdlController {
+    type Event = Event;
+
+    fn update(&mut self, input: &mut Input, event: &Event) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(*which) {
+                Ok(pad) => {
+                    println!("controller connected: {}", pad.name());
+                    self.pads.push(pad);
+                }
+                Err(e) => println!("could not open controller {}: {}", which, e),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.pads.retain(|pad| pad.instance_id() != *which);
+                //whatever it was holding is let go
+                let (gone, kept) = self.down.iter().partition(|(pad, _)| pad == which);
+                self.down = kept;
+                for (_, button) in gone {
+                    if let Some(key) = self.key_for(button) {
+                        input.release(key);
+                    }
+                }
+                println!("controller disconnected");
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(key) = self.key_for(*button) {
+                    self.down.push((*which, *button));
+                    input.press(key);
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(key) = self.key_for(*button) {
+                    self.down.retain(|down| *down != (*which, *button));
+                    input.release(key);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+//-------------------------------------------------------------------------------------------------
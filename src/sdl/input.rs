@@ -0,0 +1,187 @@
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+
+use rust_8::frontend::InputSource;
+use rust_8::input::Input;
+
+//a host key that drives a chip8 key
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Binding {
+    //physical position, the same on every keyboard layout
+    Scancode(Scancode),
+    //whatever key has this label on the user's layout
+    Keycode(Keycode),
+}
+
+impl Binding {
+    //sdl scancode names (W, 1, Keypad 7), or key:<name> for a named key
+    pub fn from_name(name: &str) -> Option<Binding> {
+        let name = name.trim();
+        match name.strip_prefix("key:") {
+            Some(key) => Keycode::from_name(key.trim()).map(Binding::Keycode),
+            None => Scancode::from_name(name).map(Binding::Scancode),
+        }
+    }
+
+    fn matches(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> bool {
+        match self {
+            Binding::Scancode(bound) => scancode == Some(*bound),
+            Binding::Keycode(bound) => keycode == Some(*bound),
+        }
+    }
+}
+
+//chip8 keys 0-F, in the order the presets below list them
+const KEY_ORDER: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+//the VIP keypad's 4x4 grid on the 1234/QWER/ASDF/ZXCV block, by position so AZERTY and dvorak
+//get the same shape
+const QWERTY: [Scancode; 16] = [
+    Scancode::Num1,
+    Scancode::Num2,
+    Scancode::Num3,
+    Scancode::Num4,
+    Scancode::Q,
+    Scancode::W,
+    Scancode::E,
+    Scancode::R,
+    Scancode::A,
+    Scancode::S,
+    Scancode::D,
+    Scancode::F,
+    Scancode::Z,
+    Scancode::X,
+    Scancode::C,
+    Scancode::V,
+];
+
+//digits on the numpad, A-F on the keys around them
+const NUMPAD: [Scancode; 16] = [
+    Scancode::Kp1,
+    Scancode::Kp2,
+    Scancode::Kp3,
+    Scancode::KpMinus,
+    Scancode::Kp4,
+    Scancode::Kp5,
+    Scancode::Kp6,
+    Scancode::KpPlus,
+    Scancode::Kp7,
+    Scancode::Kp8,
+    Scancode::Kp9,
+    Scancode::KpEnter,
+    Scancode::KpDivide,
+    Scancode::Kp0,
+    Scancode::KpMultiply,
+    Scancode::KpPeriod,
+];
+
+//every key is pressed with the key labelled the same, like typing on the VIP's hex keypad
+const VIP: [Keycode; 16] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::C,
+    Keycode::Num4,
+    Keycode::Num5,
+    Keycode::Num6,
+    Keycode::D,
+    Keycode::Num7,
+    Keycode::Num8,
+    Keycode::Num9,
+    Keycode::E,
+    Keycode::A,
+    Keycode::Num0,
+    Keycode::B,
+    Keycode::F,
+];
+
+//-----------------------------------------------SDL Input-----------------------------------------
+//maps host keys onto the hex keypad, each chip8 key can have any number of bindings
+pub struct SdlInput {
+    pub bindings: [Vec<Binding>; 16],
+}
+
+impl SdlInput {
+    pub fn preset(name: &str) -> Option<SdlInput> {
+        let grid: Vec<Binding> = match name.to_lowercase().as_str() {
+            "qwerty" | "grid" | "default" => QWERTY.iter().map(|s| Binding::Scancode(*s)).collect(),
+            "numpad" | "keypad" => NUMPAD.iter().map(|s| Binding::Scancode(*s)).collect(),
+            "vip" | "hex" => VIP.iter().map(|k| Binding::Keycode(*k)).collect(),
+            _ => return None,
+        };
+
+        let mut input = SdlInput {
+            bindings: Default::default(),
+        };
+        for (key, binding) in KEY_ORDER.iter().zip(grid) {
+            input.bindings[*key as usize].push(binding);
+        }
+        Some(input)
+    }
+
+    //preset (qwerty if None) with single keys rebound on top, bindings are comma separated
+    pub fn configure(preset: Option<&str>, keys: &[(u8, String)]) -> Result<SdlInput, String> {
+        let preset = preset.unwrap_or("qwerty");
+        let mut input = SdlInput::preset(preset).ok_or(format!("unknown keymap: {}", preset))?;
+
+        for (key, names) in keys.iter() {
+            let mut bindings = Vec::new();
+            for name in names.split(',') {
+                bindings.push(Binding::from_name(name).ok_or(format!("unknown key: {}", name))?);
+            }
+            input.bindings[*key as usize] = bindings;
+        }
+        Ok(input)
+    }
+
+    //whether this key event drives the keypad. bound keys go to the keypad before anything
+    //else gets to look at them, so a binding always wins over a hotkey
+    pub fn binds(&self, event: &Event) -> bool {
+        match event {
+            Event::KeyDown {
+                keycode, scancode, ..
+            }
+            | Event::KeyUp {
+                keycode, scancode, ..
+            } => self.map(*keycode, *scancode).is_some(),
+            _ => false,
+        }
+    }
+
+    fn map(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<u8> {
+        self.bindings
+            .iter()
+            .position(|bindings| bindings.iter().any(|b| b.matches(keycode, scancode)))
+            .map(|key| key as u8)
+    }
+}
+
+impl InputSource for SdlInput {
+    type Event = Event;
+
+    fn update(&mut self, input: &mut Input, event: &Event) {
+        match event {
+            Event::KeyDown {
+                keycode,
+                scancode,
+                repeat: false,
+                ..
+            } => {
+                if let Some(key) = self.map(*keycode, *scancode) {
+                    input.press(key);
+                }
+            }
+            Event::KeyUp {
+                keycode, scancode, ..
+            } => {
+                if let Some(key) = self.map(*keycode, *scancode) {
+                    input.release(key);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,8 @@
+//sdl implementations of the frontend traits from the core
+pub mod afterglow;
+pub mod audio;
+pub mod controller;
+pub mod input;
+pub mod palette;
+pub mod upscale;
+pub mod video;
@@ -0,0 +1,108 @@
+use sdl2::pixels::Color;
+
+//xo-chip has 2 bitplanes, so 4 colours, but palettes have room for 16 for 4 plane displays
+pub const PALETTE_SIZE: usize = 16;
+
+//indexed by pixel value: background, plane 1, plane 2, both planes, then the rest of the 16
+const PALETTES: &[(&str, &[u32])] = &[
+    ("default", &[0x000000, 0xFF0000, 0xFFAA00, 0xFFFFFF]),
+    //green phosphor monitor
+    ("green", &[0x0C1A0C, 0x33FF33, 0x1E8C1E, 0xB0FFB0]),
+    //amber phosphor monitor
+    ("amber", &[0x1A1000, 0xFFB000, 0x8C5C00, 0xFFE4A8]),
+    //dark pixels on a greenish lcd, like early handhelds
+    ("lcd", &[0x9BBC0F, 0x0F380F, 0x306230, 0x000000]),
+    ("contrast", &[0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF]),
+    //Okabe-Ito colours, told apart with any kind of colour blindness
+    ("colourblind", &[0x000000, 0xE69F00, 0x56B4E9, 0xF0E442]),
+    //the 16 cga colours
+    (
+        "cga",
+        &[
+            0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA,
+            0x555555, 0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+        ],
+    ),
+];
+
+//------------------------------------------------Palette------------------------------------------
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colours: [Color; PALETTE_SIZE],
+}
+
+impl Palette {
+    //palettes with fewer than 16 colours repeat the last one, so a 2 colour palette draws both
+    //planes in the foreground colour
+    pub fn new(name: &str, colours: &[Color]) -> Palette {
+        let last = *colours.last().unwrap_or(&Color::BLACK);
+        let mut full = [last; PALETTE_SIZE];
+        for (slot, colour) in full.iter_mut().zip(colours) {
+            *slot = *colour;
+        }
+        Palette {
+            name: name.to_string(),
+            colours: full,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        let name = name.to_lowercase();
+        let name = match name.as_str() {
+            "classic" => "green",
+            "highcontrast" | "high-contrast" => "contrast",
+            "colorblind" => "colourblind",
+            name => name,
+        };
+        let (name, colours) = PALETTES.iter().find(|(preset, _)| *preset == name)?;
+        let colours: Vec<Color> = colours.iter().map(|rgb| rgb_colour(*rgb)).collect();
+        Some(Palette::new(name, &colours))
+    }
+
+    //a palette name, or 2 to 16 comma separated hex colours like #000000,#33ff33
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        if !spec.contains(',') {
+            if let Some(palette) = Palette::from_name(spec) {
+                return Ok(palette);
+            }
+        }
+
+        let mut colours = Vec::new();
+        for colour in spec.split(',') {
+            let hex = colour.trim().trim_start_matches('#');
+            let rgb = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)
+                .ok_or(format!("unknown palette or bad colour: {}", colour))?;
+            colours.push(rgb_colour(rgb));
+        }
+        if !(2..=PALETTE_SIZE).contains(&colours.len()) {
+            return Err(format!(
+                "a palette needs 2 to {} colours, got {}",
+                PALETTE_SIZE,
+                colours.len()
+            ));
+        }
+        Ok(Palette::new("custom", &colours))
+    }
+
+    //every named palette, in the order the palette key cycles through them
+    pub fn all() -> Vec<Palette> {
+        PALETTES
+            .iter()
+            .filter_map(|(name, _)| Palette::from_name(name))
+            .collect()
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::from_name("default").unwrap()
+    }
+}
+
+fn rgb_colour(rgb: u32) -> Color {
+    Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,198 @@
+use sdl2::pixels::Color;
+
+//how much of its colour a pixel keeps under a scanline or grid line
+const OVERLAY_SHADE: f32 = 0.45;
+
+//smoothing that rounds off the staircase edges of the picture by looking at each pixel's
+//neighbours, making every pixel 2x2 or 3x3
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Smoothing {
+    None,
+    //also known as EPX, the same rules
+    Scale2x,
+    Scale3x,
+}
+
+impl Smoothing {
+    pub fn from_name(name: &str) -> Option<Smoothing> {
+        match name.to_lowercase().as_str() {
+            "none" | "off" => Some(Smoothing::None),
+            "scale2x" | "epx" | "2x" => Some(Smoothing::Scale2x),
+            "scale3x" | "3x" => Some(Smoothing::Scale3x),
+            _ => None,
+        }
+    }
+
+    fn factor(&self) -> usize {
+        match self {
+            Smoothing::None => 1,
+            Smoothing::Scale2x => 2,
+            Smoothing::Scale3x => 3,
+        }
+    }
+}
+
+//dark lines drawn over the picture, one per emulated pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Overlay {
+    None,
+    //under every row, like a crt
+    Scanlines,
+    //around every pixel, like an lcd
+    Grid,
+}
+
+impl Overlay {
+    pub fn from_name(name: &str) -> Option<Overlay> {
+        match name.to_lowercase().as_str() {
+            "none" | "off" => Some(Overlay::None),
+            "scanlines" | "crt" => Some(Overlay::Scanlines),
+            "grid" | "lcd" => Some(Overlay::Grid),
+            _ => None,
+        }
+    }
+}
+
+//a pixel becomes 2x2, corners take a neighbour's colour where two neighbours meet diagonally
+//through it. the block comes back in the top left of a 3x3
+fn scale2x(at: impl Fn(isize, isize) -> Color) -> [[Color; 3]; 3] {
+    let (p, a, b, c, d) = (at(0, 0), at(0, -1), at(1, 0), at(-1, 0), at(0, 1));
+    let mut block = [[p; 3]; 3];
+    if c == a && c != d && a != b {
+        block[0][0] = a;
+    }
+    if a == b && a != c && b != d {
+        block[0][1] = b;
+    }
+    if d == c && d != b && c != a {
+        block[1][0] = c;
+    }
+    if b == d && b != a && d != c {
+        block[1][1] = d;
+    }
+    block
+}
+
+//a pixel becomes 3x3, the same idea as scale2x with edges and corners looked at separately
+fn scale3x(at: impl Fn(isize, isize) -> Color) -> [[Color; 3]; 3] {
+    let (a, b, c) = (at(-1, -1), at(0, -1), at(1, -1));
+    let (d, e, f) = (at(-1, 0), at(0, 0), at(1, 0));
+    let (g, h, i) = (at(-1, 1), at(0, 1), at(1, 1));
+    let mut block = [[e; 3]; 3];
+    if b != h && d != f {
+        if d == b {
+            block[0][0] = d;
+        }
+        if (d == b && e != c) || (b == f && e != a) {
+            block[0][1] = b;
+        }
+        if b == f {
+            block[0][2] = f;
+        }
+        if (d == b && e != g) || (d == h && e != a) {
+            block[1][0] = d;
+        }
+        if (b == f && e != i) || (h == f && e != c) {
+            block[1][2] = f;
+        }
+        if d == h {
+            block[2][0] = d;
+        }
+        if (d == h && e != i) || (h == f && e != g) {
+            block[2][1] = h;
+        }
+        if h == f {
+            block[2][2] = f;
+        }
+    }
+    block
+}
+
+fn shade(colour: Color) -> Color {
+    Color::RGB(
+        (colour.r as f32 * OVERLAY_SHADE) as u8,
+        (colour.g as f32 * OVERLAY_SHADE) as u8,
+        (colour.b as f32 * OVERLAY_SHADE) as u8,
+    )
+}
+
+//------------------------------------------------Upscaler-----------------------------------------
+//blows the picture up on the cpu before it is uploaded, so none of this needs a gpu. smoothing
+//runs first, then the result is made big enough for an overlay line to fit in every pixel
+pub struct Upscaler {
+    pub smoothing: Smoothing,
+    pub overlay: Overlay,
+    //the smoothed picture, kept around so drawing does not allocate
+    smoothed: Vec<Color>,
+}
+
+impl Upscaler {
+    pub fn new(smoothing: Smoothing, overlay: Overlay) -> Upscaler {
+        Upscaler {
+            smoothing,
+            overlay,
+            smoothed: Vec::new(),
+        }
+    }
+
+    //output pixels per emulated pixel, across and down
+    pub fn factor(&self) -> usize {
+        let smooth = self.smoothing.factor();
+        match self.overlay {
+            Overlay::None => smooth,
+            //an overlay line takes up one output pixel, leave at least 2 for the pixel itself
+            _ => smooth * if smooth == 1 { 3 } else { 2 },
+        }
+    }
+
+    //scales a width x height picture up by factor, handing every output pixel to put
+    pub fn scale(
+        &mut self,
+        src: &[Color],
+        width: usize,
+        height: usize,
+        mut put: impl FnMut(usize, usize, Color),
+    ) {
+        let smooth = self.smoothing.factor();
+        let smooth_width = width * smooth;
+        self.smoothed.clear();
+        self.smoothed
+            .resize(smooth_width * height * smooth, Color::BLACK);
+
+        for y in 0..height {
+            for x in 0..width {
+                //neighbours off the edge are the pixel itself
+                let at = |dx: isize, dy: isize| {
+                    let nx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+                    let ny = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+                    src[ny * width + nx]
+                };
+                let block = match self.smoothing {
+                    Smoothing::None => [[at(0, 0); 3]; 3],
+                    Smoothing::Scale2x => scale2x(at),
+                    Smoothing::Scale3x => scale3x(at),
+                };
+                for (by, line) in block.iter().take(smooth).enumerate() {
+                    for (bx, colour) in line.iter().take(smooth).enumerate() {
+                        self.smoothed[(y * smooth + by) * smooth_width + x * smooth + bx] = *colour;
+                    }
+                }
+            }
+        }
+
+        let factor = self.factor();
+        let repeat = factor / smooth;
+        for y in 0..height * factor {
+            for x in 0..width * factor {
+                let colour = self.smoothed[(y / repeat) * smooth_width + x / repeat];
+                let on_line = match self.overlay {
+                    Overlay::None => false,
+                    Overlay::Scanlines => y % factor == factor - 1,
+                    Overlay::Grid => y % factor == factor - 1 || x % factor == factor - 1,
+                };
+                put(x, y, if on_line { shade(colour) } else { colour });
+            }
+        }
+    }
+}
+//-------------------------------------------------------------------------------------------------
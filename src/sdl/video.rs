@@ -0,0 +1,221 @@
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
+use sdl2::Sdl;
+
+use rust_8::display::{Display, MAX_HEIGHT, MAX_WIDTH};
+use rust_8::frontend::Video;
+
+use crate::sdl::afterglow::Afterglow;
+use crate::sdl::palette::Palette;
+use crate::sdl::upscale::Upscaler;
+
+//RGB24
+const BYTES_PER_PIXEL: usize = 3;
+
+//the picture is always 2:1, whatever is left of the window around it is black
+const LETTERBOX: Color = Color::BLACK;
+
+//how the picture is fitted into the window
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scaling {
+    //as big as fits, pixels can end up a fraction of a window pixel apart in size
+    Fit,
+    //every emulated pixel is a whole number of window pixels, as many as fit
+    Integer,
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        match name.to_lowercase().as_str() {
+            "fit" | "fractional" => Some(Scaling::Fit),
+            "integer" | "int" => Some(Scaling::Integer),
+            _ => None,
+        }
+    }
+}
+
+//-----------------------------------------------SDL Video-----------------------------------------
+//the framebuffer goes into one streaming texture the size of the biggest mode, which the renderer
+//stretches over the window. that is one upload and one copy per frame, however many sprites
+//were drawn
+pub struct SdlVideo<'a> {
+    //NOTE: nessecary for display to actually draw things
+    pub canvas: Canvas<Window>,
+    //the streaming texture borrows its creator, so that has to be owned by whoever owns this
+    texture: Texture<'a>,
+    pub scaling: Scaling,
+    //the palette key cycles through these, the one picked at startup comes first
+    palettes: Vec<Palette>,
+    palette: usize,
+    //fading and blending between frames, off until strengths are set
+    pub afterglow: Afterglow,
+    //smoothing and overlays, the texture is made big enough for them
+    upscaler: Upscaler,
+    //colour of every emulated pixel this frame, before upscaling
+    frame: Vec<Color>,
+}
+
+impl<'a> SdlVideo<'a> {
+    //the window starts at scale window pixels per lores pixel and can be resized from there.
+    //the software renderer needs no gpu, but it cannot wait for the display to refresh, so
+    //vsync gets the accelerated one
+    pub fn canvas(sdl_context: &Sdl, vsync: bool, scale: u32, fullscreen: bool) -> Canvas<Window> {
+        let video_subsystem = sdl_context.video().unwrap();
+
+        let mut window = video_subsystem
+            .window("rust-8", 64 * scale, 32 * scale)
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+        if fullscreen {
+            window.set_fullscreen(FullscreenType::Desktop).unwrap();
+        }
+
+        let canvas = window.into_canvas();
+        let canvas = if vsync {
+            canvas.accelerated().present_vsync()
+        } else {
+            canvas.software()
+        };
+        canvas.build().unwrap()
+    }
+
+    //textures comes from canvas.texture_creator()
+    pub fn new(
+        canvas: Canvas<Window>,
+        textures: &'a TextureCreator<WindowContext>,
+        scaling: Scaling,
+        upscaler: Upscaler,
+    ) -> SdlVideo<'a> {
+        let texture = SdlVideo::texture_for(textures, &upscaler);
+
+        SdlVideo {
+            canvas,
+            texture,
+            scaling,
+            palettes: Palette::all(),
+            palette: 0,
+            afterglow: Afterglow::new(0.0, 0.0),
+            upscaler,
+            frame: vec![Color::BLACK; MAX_WIDTH * MAX_HEIGHT],
+        }
+    }
+
+    //big enough for the biggest mode after upscaling
+    fn texture_for(
+        textures: &'a TextureCreator<WindowContext>,
+        upscaler: &Upscaler,
+    ) -> Texture<'a> {
+        let factor = upscaler.factor() as u32;
+        textures
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                MAX_WIDTH as u32 * factor,
+                MAX_HEIGHT as u32 * factor,
+            )
+            .unwrap()
+    }
+
+    //start with this palette, the named ones follow it in the cycle
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palettes = Palette::all();
+        self.palettes.retain(|named| *named != palette);
+        self.palettes.insert(0, palette);
+        self.palette = 0;
+    }
+
+    //switch to the next palette and return it
+    pub fn next_palette(&mut self) -> &Palette {
+        self.palette = (self.palette + 1) % self.palettes.len();
+        &self.palettes[self.palette]
+    }
+
+    //call after every emulated frame, so fading runs at the emulated frame rate and not at
+    //however often the picture gets drawn
+    pub fn end_frame(&mut self, disp: &Display) {
+        let colours = &self.palettes[self.palette].colours;
+        self.afterglow.step(disp, colours);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.canvas.window_mut().set_title(title).unwrap();
+    }
+
+    //borderless fullscreen at the desktop resolution, so switching is quick
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(state).unwrap();
+    }
+
+    //where a width x height picture goes in the window: as big as the scaling allows, centered
+    fn viewport(&self, width: u32, height: u32) -> Rect {
+        let (w, h) = self.canvas.output_size().unwrap();
+        let (view_w, view_h) = match self.scaling {
+            //the same rect in every mode, hires pixels are simply half the size
+            Scaling::Fit => {
+                let scale = (w as f32 / width as f32).min(h as f32 / height as f32);
+                (
+                    (width as f32 * scale) as u32,
+                    (height as f32 * scale) as u32,
+                )
+            }
+            //never smaller than one window pixel per emulated pixel, even if that gets cut off
+            Scaling::Integer => {
+                let scale = (w / width).min(h / height).max(1);
+                (width * scale, height * scale)
+            }
+        };
+        Rect::new(
+            (w as i32 - view_w as i32) / 2,
+            (h as i32 - view_h as i32) / 2,
+            view_w.max(1),
+            view_h.max(1),
+        )
+    }
+}
+
+impl Video for SdlVideo<'_> {
+    fn draw(&mut self, disp: &Display) {
+        let (width, height) = (disp.width(), disp.height());
+        let colours = &self.palettes[self.palette].colours;
+        for y in 0..height {
+            for x in 0..width {
+                self.frame[y * width + x] = if self.afterglow.active() {
+                    self.afterglow.colour(disp, colours, x, y)
+                } else {
+                    colours[disp.pixel(x, y) as usize]
+                };
+            }
+        }
+
+        //only the top left of the texture is used in lores
+        let factor = self.upscaler.factor();
+        let area = Rect::new(0, 0, (width * factor) as u32, (height * factor) as u32);
+        let frame = &self.frame[..width * height];
+        let upscaler = &mut self.upscaler;
+        self.texture
+            .with_lock(area, |buf, pitch| {
+                upscaler.scale(frame, width, height, |x, y, colour| {
+                    let i = y * pitch + x * BYTES_PER_PIXEL;
+                    buf[i] = colour.r;
+                    buf[i + 1] = colour.g;
+                    buf[i + 2] = colour.b;
+                });
+            })
+            .unwrap();
+
+        let viewport = self.viewport(width as u32, height as u32);
+        self.canvas.set_draw_color(LETTERBOX);
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, area, viewport).unwrap();
+        self.canvas.present();
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -0,0 +1,50 @@
+use sdl2::keyboard::{Mod, Scancode};
+use std::fs;
+
+use rust_8::CPU;
+
+//------------------------------------------Save slots---------------------------------------------
+//F1-F10 load slots 1-10, shift+F1-F10 save them. states live next to the rom as <rom>.s<N>
+
+pub fn slot_for(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::F1 => Some(1),
+        Scancode::F2 => Some(2),
+        Scancode::F3 => Some(3),
+        Scancode::F4 => Some(4),
+        Scancode::F5 => Some(5),
+        Scancode::F6 => Some(6),
+        Scancode::F7 => Some(7),
+        Scancode::F8 => Some(8),
+        Scancode::F9 => Some(9),
+        Scancode::F10 => Some(10),
+        _ => None,
+    }
+}
+
+pub fn slot_path(rom: &str, slot: u8) -> String {
+    format!("{}.s{}", rom, slot)
+}
+
+//save or load depending on shift, failures are reported and otherwise ignored.
+//loads is false while a movie runs, saving is still fine then
+pub fn handle(cpu: &mut CPU, rom: &str, slot: u8, keymod: Mod, loads: bool) {
+    let path = slot_path(rom, slot);
+    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        match fs::write(&path, cpu.save_state()) {
+            Ok(()) => println!("saved state to slot {} ({})", slot, path),
+            Err(e) => println!("could not save slot {}: {}", slot, e),
+        }
+    } else if !loads {
+        println!("loading states is off while a movie is recording or playing");
+    } else {
+        match fs::read(&path) {
+            Ok(data) => match cpu.load_state(&data) {
+                Ok(()) => println!("loaded state from slot {} ({})", slot, path),
+                Err(e) => println!("could not load slot {}: {}", slot, e),
+            },
+            Err(e) => println!("could not read slot {}: {}", slot, e),
+        }
+    }
+}
+//-------------------------------------------------------------------------------------------------
@@ -1,4 +1,11 @@
 //use std::time::Instant;
+use std::f32::consts::PI;
+
+//peak level of generated samples at full volume, leaves plenty of headroom
+pub const AMPLITUDE: f32 = 0.25;
+//never buffer more than this many seconds of samples nobody has collected
+const MAX_BUFFERED: f32 = 1.0;
+
 //-----------------------------------------------Sound---------------------------------------------
 pub struct Sound {
     //freq in Hz at which the times should decrease while non-zero
@@ -6,12 +13,23 @@ pub struct Sound {
     pub freq: f32,
     pub dt: u8,
     pub st: u8,
-    //instant representing the last time the timers were updatd
-    //use this in conjunction with .elapsed to update @ 60Hz
-    //pub dt_lu: Instant,
-    //pub st_lu: Instant,
-    pub dt_lu: i32,
-    pub st_lu: i32,
+
+    //xo-chip audio: 128 1-bit samples played msb first, looping, while st is non-zero
+    pub pattern: [u8; 16],
+    //set once a program runs F002, until then the beeper plays instead
+    pub pattern_loaded: bool,
+    //xo-chip pitch register, 64 is the default 4000 samples per second
+    pub pitch: u8,
+    //position in the pattern, in pattern samples
+    pub phase: f64,
+
+    //tone used by plain chip8 programs
+    pub beeper: Beeper,
+
+    //output rate, set by whoever is collecting samples
+    pub sample_rate: u32,
+    //one emulated frame worth of samples is appended here per render_frame
+    pub samples: Vec<f32>,
 }
 
 impl Sound {
@@ -20,11 +38,146 @@ impl Sound {
             freq: 60.0,
             dt: 0,
             st: 0,
-            //dt_lu: Instant::now(),
-            //st_lu: Instant::now(),
-            dt_lu: 0,
-            st_lu: 0,
+            pattern: [0; 16],
+            pattern_loaded: false,
+            pitch: 64,
+            phase: 0.0,
+            beeper: Beeper::new(),
+            sample_rate: 44100,
+            samples: Vec::new(),
+        }
+    }
+
+    //pattern samples per second for the current pitch register
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    //fills out with mono samples at sample_rate, silence while the sound timer is 0
+    pub fn generate(&mut self, out: &mut [f32], sample_rate: u32) {
+        if self.st == 0 || self.beeper.muted {
+            out.iter_mut().for_each(|sample| *sample = 0.0);
+            return;
+        }
+        if !self.pattern_loaded {
+            self.beeper.generate(out, sample_rate);
+            return;
         }
+
+        let step = self.playback_rate() / sample_rate as f64;
+        let level = AMPLITUDE * self.beeper.volume;
+
+        for sample in out.iter_mut() {
+            let bit = self.phase as usize % 128;
+            let on = (self.pattern[bit / 8] >> (7 - bit % 8)) & 0x1 == 1;
+            *sample = if on { level } else { -level };
+            self.phase = (self.phase + step) % 128.0;
+        }
+    }
+
+    //end of an emulated frame: render its audio, then count both timers down once
+    pub fn tick(&mut self) {
+        self.render_frame();
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    //append one emulated 60Hz frame of audio to samples.
+    //called once per emulated frame, so audio follows the emulator when it runs fast or pauses
+    pub fn render_frame(&mut self) {
+        let count = (self.sample_rate as f32 / self.freq) as usize;
+        let start = self.samples.len();
+        self.samples.resize(start + count, 0.0);
+
+        let mut frame = std::mem::take(&mut self.samples);
+        self.generate(&mut frame[start..], self.sample_rate);
+
+        //nobody is listening, drop the oldest samples instead of growing forever
+        let max = (self.sample_rate as f32 * MAX_BUFFERED) as usize;
+        if frame.len() > max {
+            frame.drain(..frame.len() - max);
+        }
+        self.samples = frame;
+    }
+
+    //hand over everything rendered since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+impl Default for Sound {
+    fn default() -> Sound {
+        Sound::new()
+    }
+}
+//----------------------
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+}
+
+//simple tone generator for the chip8 buzzer
+pub struct Beeper {
+    pub waveform: Waveform,
+    //tone in Hz
+    pub freq: f32,
+    //0.0 - 1.0, also scales xo-chip patterns
+    pub volume: f32,
+    pub muted: bool,
+    //position in the current cycle, 0.0 - 1.0
+    pub phase: f32,
+}
+
+impl Beeper {
+    pub fn new() -> Beeper {
+        Beeper {
+            waveform: Waveform::Square,
+            freq: 440.0,
+            volume: 1.0,
+            muted: false,
+            phase: 0.0,
+        }
+    }
+
+    pub fn generate(&mut self, out: &mut [f32], sample_rate: u32) {
+        let step = self.freq / sample_rate as f32;
+        let level = AMPLITUDE * self.volume;
+
+        for sample in out.iter_mut() {
+            let value = match self.waveform {
+                Waveform::Square => {
+                    if self.phase < 0.5 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+                Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            };
+            *sample = value * level;
+            self.phase = (self.phase + step) % 1.0;
+        }
+    }
+}
+
+impl Default for Beeper {
+    fn default() -> Beeper {
+        Beeper::new()
     }
 }
 //-------------------------------------------------------------------------------------------------
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use crate::cpu::CPU;
+use crate::error::EmuError;
+
+//-----------------------------------------------SYS-----------------------------------------------
+//0NNN jumped into 1802 machine code on the VIP. rust-8 can not run that, but the handful of
+//routines programs actually called are well known and can be stood in for by native code.
+//a handler does the routine's work, the cpu moves past the 0NNN afterwards
+pub type SysHandler = fn(&mut CPU) -> Result<(), EmuError>;
+
+pub struct SysCalls {
+    handlers: HashMap<u16, SysHandler>,
+}
+
+impl SysCalls {
+    //no handlers, every 0NNN is a bad opcode
+    pub fn new() -> SysCalls {
+        SysCalls {
+            handlers: HashMap::new(),
+        }
+    }
+
+    //the routines vip programs are known to call
+    pub fn vip() -> SysCalls {
+        let mut sys = SysCalls::new();
+        //hires chip-8 (64x64) patches its own screen clear in at 0x230 and calls it instead of 00E0
+        sys.register(0x230, |cpu| {
+            cpu.disp.clear_disp();
+            Ok(())
+        });
+        sys
+    }
+
+    pub fn register(&mut self, addr: u16, handler: SysHandler) {
+        self.handlers.insert(addr & 0xFFF, handler);
+    }
+
+    pub fn get(&self, addr: u16) -> Option<SysHandler> {
+        self.handlers.get(&(addr & 0xFFF)).copied()
+    }
+
+    //every address with a handler, lowest first
+    pub fn addresses(&self) -> Vec<u16> {
+        let mut addresses: Vec<u16> = self.handlers.keys().copied().collect();
+        addresses.sort_unstable();
+        addresses
+    }
+}
+
+impl Default for SysCalls {
+    fn default() -> SysCalls {
+        SysCalls::new()
+    }
+}
+//-------------------------------------------------------------------------------------------------